@@ -0,0 +1,202 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use crate::{GitCredentials, HostKeyPolicy, ProxyConfig, SharedCredentialProvider};
+
+use color_eyre::eyre::{eyre, Result};
+use git_url_parse::GitUrl;
+use tracing::debug;
+
+/// Returns `url` with `credentials` applied for use with the `git` CLI,
+/// along with any `-c` config needed (e.g. `core.sshcommand` for ssh keys or a `host_key_policy`,
+/// and `http.proxy` for a `proxy` url).
+/// A custom `provider` only supplies `git2` callbacks, so it can't be used with the CLI
+pub(crate) fn authenticated_url(
+    url: &GitUrl,
+    credentials: &Option<GitCredentials>,
+    provider: &Option<SharedCredentialProvider>,
+    host_key_policy: HostKeyPolicy,
+    proxy: &ProxyConfig,
+) -> Result<(GitUrl, Vec<String>)> {
+    if provider.is_some() {
+        return Err(eyre!(
+            "Custom credential providers are not supported by operations that use the git CLI"
+        ));
+    }
+
+    let mut cli_url = url.trim_auth();
+    let mut config = Vec::new();
+    let mut ssh_options = Vec::new();
+
+    match credentials {
+        Some(GitCredentials::SshKey {
+            username,
+            private_key,
+            ..
+        }) => {
+            cli_url.user = Some(username.to_string());
+
+            let privkey_path = if let Ok(path) = private_key.clone().into_os_string().into_string()
+            {
+                path
+            } else {
+                return Err(eyre!("Couldn't convert path to string"));
+            };
+
+            ssh_options.push(format!("-i {privkey_path}"));
+        }
+        Some(GitCredentials::UserPassPlaintext { username, password }) => {
+            cli_url.user = Some(username.to_string());
+            cli_url.token = Some(password.to_string());
+        }
+        None => {}
+    }
+
+    // Strict is the default for ssh without a terminal to prompt on
+    match host_key_policy {
+        HostKeyPolicy::Strict => {}
+        HostKeyPolicy::AcceptNew => ssh_options.push("-o StrictHostKeyChecking=accept-new".into()),
+        HostKeyPolicy::AcceptAll => {
+            ssh_options.push("-o StrictHostKeyChecking=no -o UserKnownHostsFile=/dev/null".into())
+        }
+    }
+
+    if !ssh_options.is_empty() {
+        config.push(format!("core.sshcommand=ssh {}", ssh_options.join(" ")));
+    }
+
+    config.extend(proxy.cli_config());
+
+    Ok((cli_url, config))
+}
+
+/// Returns the fingerprints of the keys for `host` in the user's and system's `known_hosts`,
+/// using `ssh-keygen`. Fingerprints look like `SHA256:<base64>`, matching `sha256_fingerprint()`.
+/// `host` should be `[host]:port` for non-default ports
+pub(crate) fn known_host_fingerprints(host: &str) -> Result<Vec<String>> {
+    let mut known_hosts = vec![PathBuf::from("/etc/ssh/ssh_known_hosts")];
+    if let Some(home) = std::env::var_os("HOME") {
+        known_hosts.push(PathBuf::from(home).join(".ssh/known_hosts"));
+    }
+
+    let mut fingerprints = Vec::new();
+
+    for file in known_hosts.iter().filter(|f| f.exists()) {
+        // Exits with an error if the host isn't found, and handles hashed hostnames for us
+        let found = if let Ok(found) = Command::new("ssh-keygen")
+            .arg("-F")
+            .arg(host)
+            .arg("-f")
+            .arg(file)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+        {
+            found
+        } else {
+            return Err(eyre!("Failed to run ssh-keygen. Is ssh installed?"));
+        };
+
+        let entries: Vec<u8> = String::from_utf8_lossy(&found.stdout)
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+            .flat_map(|line| format!("{line}\n").into_bytes())
+            .collect();
+
+        if !found.status.success() || entries.is_empty() {
+            continue;
+        }
+
+        let mut child = Command::new("ssh-keygen")
+            .args(["-l", "-E", "sha256", "-f", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&entries)?;
+        }
+
+        let listed = child.wait_with_output()?;
+
+        // Each line looks like `256 SHA256:<base64> host (ED25519)`
+        fingerprints.extend(
+            String::from_utf8_lossy(&listed.stdout)
+                .lines()
+                .filter_map(|line| line.split_whitespace().nth(1))
+                .map(str::to_string),
+        );
+    }
+
+    Ok(fingerprints)
+}
+
+/// Format a SHA256 host key hash like `ssh-keygen -l`, as `SHA256:` and unpadded base64
+pub(crate) fn sha256_fingerprint(hash: &[u8; 32]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::from("SHA256:");
+
+    for chunk in hash.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        for i in 0..=chunk.len() {
+            encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+
+    encoded
+}
+
+/// Returns a `Command` for the `git` CLI, running in `dir` if provided
+pub(crate) fn git_command(dir: Option<&Path>) -> Command {
+    let mut command = Command::new("git");
+
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    command
+}
+
+/// Run `git` with `args`, in `dir` if provided
+pub(crate) fn run_git(dir: Option<&Path>, args: &[&str]) -> Result<Output> {
+    let mut command = git_command(dir);
+    command.args(args);
+
+    run(&mut command)
+}
+
+/// Run a `git` command to completion.
+/// Returns `Err` containing the exit status and stderr if the command fails
+pub(crate) fn run(command: &mut Command) -> Result<Output> {
+    let output = if let Ok(output) = command.output() {
+        output
+    } else {
+        return Err(eyre!("Failed to run git. Is the git CLI installed?"));
+    };
+
+    debug!("git exited with {}", output.status);
+
+    if !output.status.success() {
+        return Err(eyre!(
+            "git exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(output)
+}
@@ -1,12 +1,20 @@
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
-
-use crate::{GitCredentials, GitRepo, GitRepoCloneRequest, GitRepoInfo};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::cli;
+use crate::{
+    CredentialConfig, CredentialProvider, GitCredentials, GitMetaError, GitRepo,
+    GitRepoCloneRequest, GitRepoInfo, HostKeyPolicy, ProxyConfig, SharedCredentialProvider,
+    UrlKind,
+};
 use git_url_parse::GitUrl;
 
-use color_eyre::eyre::{eyre, Result};
-use tracing::{debug, info};
+use chrono::prelude::*;
+use color_eyre::eyre::{eyre, Context, Result};
+use git2::Repository;
+use tracing::{debug, info, instrument};
 
 impl GitRepoCloneRequest {
     /// Create a new `GitRepo` with `url`.
@@ -22,13 +30,25 @@ impl GitRepoCloneRequest {
         Ok(Self {
             url,
             credentials: None,
+            credential_provider: None,
             head: None,
             branch: None,
+            tag: None,
             path: None,
+            sparse_paths: None,
+            reference_repo: None,
+            keep_reference: false,
+            max_objects: None,
+            host_key_policy: HostKeyPolicy::default(),
+            no_checkout: false,
+            proxy: ProxyConfig::default(),
+            shallow_since: None,
         })
     }
 
-    /// Set the location of `GitRepo` on the filesystem
+    /// Set the location of `GitRepo` on the filesystem.
+    /// The path is canonicalized, so any symlinks in `path` are resolved.
+    /// Use `with_path_raw()` to keep `path` as given
     pub fn with_path(mut self, path: PathBuf) -> Result<Self> {
         // We want to get the absolute path of the directory of the repo
         self.path = if let Ok(p) = fs::canonicalize(path) {
@@ -39,6 +59,18 @@ impl GitRepoCloneRequest {
         Ok(self)
     }
 
+    /// Set the location of `GitRepo` on the filesystem, without canonicalizing.
+    /// Unlike `with_path()`, symlinks in `path` are not resolved. This is useful when
+    /// intentionally working through a symlinked directory, like some CI workspaces
+    pub fn with_path_raw(mut self, path: PathBuf) -> Result<Self> {
+        if !path.exists() {
+            return Err(eyre!("Directory was not found"));
+        }
+
+        self.path = Some(path);
+        Ok(self)
+    }
+
     /// Intended to be set with the remote name branch of GitRepo
     pub fn with_branch(mut self, branch: Option<String>) -> Self {
         if let Some(b) = branch {
@@ -47,6 +79,13 @@ impl GitRepoCloneRequest {
         self
     }
 
+    /// Clone at `tag` instead of a branch, leaving the clone in detached HEAD at the tag's commit,
+    /// like `git clone --branch <tag>`. Can't be used along with `with_branch()`
+    pub fn with_tag(mut self, tag: String) -> Self {
+        self.tag = Some(tag);
+        self
+    }
+
     // TODO: Fix this for clone
     ///// Reinit `GitRepo` with commit id
     //pub fn with_commit(mut self, commit_id: Option<String>) -> Self {
@@ -55,6 +94,70 @@ impl GitRepoCloneRequest {
     //    self
     //}
 
+    /// Only check out `paths` when cloning with `git_clone_shallow()`, using cone-mode sparse checkout.
+    /// Each path is a directory relative to the repo root. Files at the repo root are always checked out.
+    pub fn with_sparse_paths(mut self, paths: Vec<String>) -> Self {
+        self.sparse_paths = Some(paths);
+        self
+    }
+
+    /// Reuse objects from a local repo at `path` while cloning, like `git clone --reference`.
+    /// This speeds up repeated clones of the same repo, such as from a cache on a shared runner.
+    /// By default, the objects are copied into the clone with `--dissociate` so the reference
+    /// repo can be removed afterwards. Only supported by `git_clone_shallow()`
+    pub fn with_reference_repo(mut self, path: PathBuf) -> Result<Self> {
+        if Repository::open(&path).is_err() {
+            return Err(eyre!("Reference repo is not a git repo: {:?}", path));
+        }
+
+        self.reference_repo = Some(path);
+        Ok(self)
+    }
+
+    /// Keep borrowing objects from the reference repo after cloning, instead of using `--dissociate`.
+    /// The clone will break if the reference repo is removed
+    pub fn with_keep_reference(mut self, keep: bool) -> Self {
+        self.keep_reference = keep;
+        self
+    }
+
+    /// Abort the clone if the remote has more than `max` objects to transfer, returning
+    /// `GitMetaError::ObjectLimitExceeded`. A guardrail against cloning a huge repo by accident.
+    /// Only supported by `git_clone()`
+    pub fn with_max_objects(mut self, max: usize) -> Self {
+        self.max_objects = Some(max);
+        self
+    }
+
+    /// Set how the host key of an ssh remote is verified. Defaults to `HostKeyPolicy::Strict`
+    pub fn with_host_key_check(mut self, policy: HostKeyPolicy) -> Self {
+        self.host_key_policy = policy;
+        self
+    }
+
+    /// Clone all of the history newer than `date` instead of a depth of 1, like `git clone --shallow-since`.
+    /// Useful for time-bounded changelogs, since the number of commits doesn't matter.
+    /// Replaces the usual `--depth=1`, since git doesn't allow both. Only supported by `git_clone_shallow()`,
+    /// and requires git 2.11 or newer
+    pub fn with_shallow_since(mut self, date: DateTime<Utc>) -> Self {
+        self.shallow_since = Some(date);
+        self
+    }
+
+    /// Set the proxy to connect through, for both `git2` and the `git` CLI. Defaults to `ProxyConfig::None`
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Clone without checking out any files, like `git clone --no-checkout`. The gitdir is populated
+    /// and HEAD is set as usual, but the worktree is left empty to be selectively populated.
+    /// Can't be used along with `with_sparse_paths()`
+    pub fn with_no_checkout(mut self, no_checkout: bool) -> Self {
+        self.no_checkout = no_checkout;
+        self
+    }
+
     /// Set `GitCredentials` for private repos.
     /// `None` indicates public repo
     pub fn with_credentials(mut self, creds: Option<GitCredentials>) -> Self {
@@ -62,6 +165,16 @@ impl GitRepoCloneRequest {
         self
     }
 
+    /// Set a custom `CredentialProvider`, used instead of `GitCredentials` for private repos.
+    /// Only supported by operations that use `git2`, not the `git` CLI
+    pub fn with_credential_provider<C: CredentialProvider + 'static>(
+        mut self,
+        provider: C,
+    ) -> Self {
+        self.credential_provider = Some(SharedCredentialProvider(Arc::new(provider)));
+        self
+    }
+
     pub fn to_repo(&self) -> GitRepo {
         self.into()
     }
@@ -70,165 +183,489 @@ impl GitRepoCloneRequest {
         self.into()
     }
 
+    /// Returns whether `self.url` is a remote url, or a local path.
+    /// Cloning requires a `Remote` url
+    pub fn url_kind(&self) -> UrlKind {
+        UrlKind::from(&self.url)
+    }
+
     // TODO: Can we make this mut self?
+    /// Clone the repo into `target` with `git2`.
+    ///
+    /// If both `branch` and `head` are set, the branch is cloned and then `head` is checked out
+    /// in detached HEAD. `head` must be reachable from the branch
+    #[instrument(
+        skip_all,
+        fields(
+            url = %self.url.trim_auth(),
+            branch = ?self.branch,
+            target = %target.as_ref().display()
+        )
+    )]
     pub fn git_clone<P: AsRef<Path>>(&self, target: P) -> Result<GitRepo> {
+        UrlKind::require_remote(&self.url, "Cloning")?;
+
+        if self.sparse_paths.is_some() {
+            return Err(eyre!(
+                "Sparse checkout is only supported by git_clone_shallow()"
+            ));
+        }
+
+        if self.reference_repo.is_some() {
+            return Err(eyre!(
+                "Reference repos are only supported by git_clone_shallow()"
+            ));
+        }
+
+        if self.shallow_since.is_some() {
+            return Err(eyre!(
+                "Shallow since a date is only supported by git_clone_shallow()"
+            ));
+        }
+
+        if self.tag.is_some() && self.branch.is_some() {
+            return Err(eyre!("Clone with either a branch or a tag, not both"));
+        }
+
         let git_info: GitRepoInfo = self.into();
-        let cb = git_info.build_git2_remotecallback()?;
+        // Needed again to fetch a tag that the clone didn't
+        let credential_config = git_info.credential_config();
+        let mut cb = credential_config.callbacks()?;
+
+        // Remember the total when we abort, since the clone only returns a generic error
+        let exceeded = Arc::new(AtomicUsize::new(0));
+        if let Some(max) = self.max_objects {
+            let exceeded = exceeded.clone();
+            cb.transfer_progress(move |progress| {
+                if progress.total_objects() > max {
+                    exceeded.store(progress.total_objects(), Ordering::SeqCst);
+                    return false;
+                }
+                true
+            });
+        }
 
         let mut builder = git2::build::RepoBuilder::new();
         let mut fetch_options = git2::FetchOptions::new();
 
         fetch_options.remote_callbacks(cb);
+        if let Some(proxy) = self.proxy.to_git2() {
+            fetch_options.proxy_options(proxy);
+        }
         builder.fetch_options(fetch_options);
 
         if let Some(b) = &self.branch {
             builder.branch(b);
         }
 
-        let repo = match builder.clone(&self.url.to_string(), target.as_ref()) {
+        if self.no_checkout {
+            let mut checkout = git2::build::CheckoutBuilder::new();
+            checkout.dry_run();
+            builder.with_checkout(checkout);
+        }
+
+        // There's no repo config yet, so this applies global url rewrites
+        let clone_url = git_info.rewritten_url()?;
+
+        let repo = match builder.clone(&clone_url, target.as_ref()) {
             Ok(repo) => repo,
-            Err(e) => return Err(eyre!("failed to clone: {}", e)),
+            Err(e) => {
+                if let Some(max_objects) = self.max_objects {
+                    let total_objects = exceeded.load(Ordering::SeqCst);
+                    if total_objects > 0 {
+                        return Err(GitMetaError::ObjectLimitExceeded {
+                            max_objects,
+                            total_objects,
+                        }
+                        .into());
+                    }
+                }
+                return Err(eyre!("failed to clone: {}", e));
+            }
         };
 
+        // Like git, keep the original url in the config. Fetches will rewrite it again
+        if clone_url != self.url.to_string() {
+            repo.remote_set_url("origin", &self.url.to_string())?;
+        }
+
+        if let Some(tag) = &self.tag {
+            Self::checkout_tag(
+                &repo,
+                &credential_config,
+                &self.proxy,
+                tag,
+                self.no_checkout,
+            )?;
+        }
+
+        let detach_at_head = self.branch.is_some() && self.head.is_some();
+        if let (Some(branch), Some(head)) = (&self.branch, &self.head) {
+            Self::checkout_commit_on_branch(&repo, branch, &head.id, self.no_checkout)?;
+        }
+
         // Ensure we don't lose the credentials while updating
         let mut git_repo: GitRepo = repo.try_into()?;
+
+        // There's no branch with an upstream in detached HEAD, so keep our url
+        if self.tag.is_some() || detach_at_head {
+            git_repo.url = self.url.trim_auth();
+        }
+
+        // The commit is on the branch we cloned, even though HEAD is detached
+        if detach_at_head {
+            git_repo.branch = self.branch.clone();
+        }
         git_repo = git_repo.with_credentials(self.credentials.clone());
+        git_repo.credential_provider = self.credential_provider.clone();
 
         Ok(git_repo)
     }
 
     // TODO: Can we make this mut self?
+    /// Clone the repo into `target` with the `git` CLI, with a history depth of 1.
+    /// If a date is set with `with_shallow_since()`, all of the history since that date is cloned instead.
+    ///
+    /// If sparse paths are set with `with_sparse_paths()`, only those directories are checked out.
+    /// Sparse checkout requires git 2.25 or newer
+    #[instrument(
+        skip_all,
+        fields(
+            url = %self.url.trim_auth(),
+            branch = ?self.branch,
+            target = %target.as_ref().display()
+        )
+    )]
     pub fn git_clone_shallow<P: AsRef<Path>>(&self, target: P) -> Result<GitRepo> {
-        let repo = if let Some(cred) = self.credentials.clone() {
-            match cred {
-                crate::GitCredentials::SshKey {
-                    username,
-                    public_key,
-                    private_key,
-                    passphrase,
-                } => {
-                    let mut parsed_uri = self.url.trim_auth();
-                    parsed_uri.user = Some(username.to_string());
-
-                    let privkey_path =
-                        if let Ok(path) = private_key.clone().into_os_string().into_string() {
-                            path
-                        } else {
-                            return Err(eyre!("Couldn't convert path to string"));
-                        };
-
-                    let shell_clone_command = if let Ok(spawn) = Command::new("git")
-                        .arg("clone")
-                        .arg(format!("{}", parsed_uri))
-                        .arg(format!("{}", target.as_ref().display()))
-                        .arg("--no-single-branch")
-                        .arg("--depth=1")
-                        .arg("--config")
-                        .arg(format!("core.sshcommand=ssh -i {privkey_path}"))
-                        .stdout(Stdio::piped())
-                        .stderr(Stdio::null())
-                        .spawn()
-                    {
-                        spawn
-                    } else {
-                        return Err(eyre!("failed to run git clone"));
-                    };
-
-                    let clone_out = if let Ok(wait) = shell_clone_command.wait_with_output() {
-                        wait
-                    } else {
-                        return Err(eyre!("failed to open stdout"));
-                    };
-
-                    debug!("Clone output: {:?}", clone_out);
-
-                    // Re-create the GitCredentials
-                    let creds = GitCredentials::SshKey {
-                        username,
-                        public_key,
-                        private_key,
-                        passphrase,
-                    };
-
-                    if let Ok(repo) = GitRepo::open(target.as_ref().to_path_buf(), None, None) {
-                        repo
-                    } else {
-                        return Err(eyre!("Failed to open shallow clone dir: {:?}", clone_out));
-                    }
-                    .with_credentials(Some(creds))
+        UrlKind::require_remote(&self.url, "Cloning")?;
+
+        if self.max_objects.is_some() {
+            return Err(eyre!("Object limits are only supported by git_clone()"));
+        }
+
+        let (clone_url, clone_config) = cli::authenticated_url(
+            &self.url,
+            &self.credentials,
+            &self.credential_provider,
+            self.host_key_policy,
+            &self.proxy,
+        )?;
+
+        info!("Url: {}", self.url.trim_auth());
+        info!("Directory: {}", target.as_ref().display());
+
+        let mut clone_command = cli::git_command(None);
+        clone_command
+            .arg("clone")
+            .arg(format!("{}", clone_url))
+            .arg(target.as_ref().as_os_str())
+            .arg("--no-single-branch");
+
+        // git doesn't allow both a depth and a date
+        if let Some(since) = &self.shallow_since {
+            clone_command.arg(format!("--shallow-since={}", since.to_rfc3339()));
+        } else {
+            clone_command.arg("--depth=1");
+        }
+
+        for config in clone_config {
+            clone_command.arg("--config").arg(config);
+        }
+
+        if let Some(tag) = &self.tag {
+            if self.branch.is_some() {
+                return Err(eyre!("Clone with either a branch or a tag, not both"));
+            }
+
+            // git checks out tags given to --branch in detached HEAD
+            clone_command.arg("--branch").arg(tag);
+        }
+
+        if let Some(reference) = &self.reference_repo {
+            clone_command.arg("--reference").arg(reference);
+
+            if !self.keep_reference {
+                clone_command.arg("--dissociate");
+            }
+        }
+
+        if self.sparse_paths.is_some() && self.no_checkout {
+            return Err(eyre!(
+                "Sparse checkout can't be used along with no checkout"
+            ));
+        }
+
+        if self.sparse_paths.is_some() || self.no_checkout {
+            // With sparse paths, we'll check out after configuring sparse checkout
+            clone_command.arg("--no-checkout");
+        }
+
+        let clone_out = cli::run(&mut clone_command).wrap_err("Failed to run git clone")?;
+        debug!("Clone output: {:?}", clone_out);
+
+        if let Some(sparse_paths) = &self.sparse_paths {
+            Self::sparse_checkout(target.as_ref(), sparse_paths)?;
+        }
+
+        let mut repo = if let Ok(repo) = GitRepo::open(target.as_ref().to_path_buf(), None, None) {
+            repo
+        } else {
+            return Err(eyre!("Failed to open shallow clone dir: {:?}", clone_out));
+        };
+
+        // There's no branch with an upstream in detached HEAD, so keep our url
+        if self.tag.is_some() {
+            repo.url = self.url.trim_auth();
+        }
+
+        Ok(repo.with_credentials(self.credentials.clone()))
+    }
+
+    /// Clone the repo into `target`, then open it with `GitRepo::open()` at the configured
+    /// `branch` and `head` commit, returning a fully-populated `GitRepo`.
+    ///
+    /// `git_clone_shallow()` is used if sparse paths, a reference repo or a shallow since date are set,
+    /// since they require it, and `git_clone()` otherwise. Shallow clones can only be opened at the tip of the
+    /// branch, so a `head` commit that isn't the tip returns `Err` instead of failing to open
+    pub fn clone_and_open<P: AsRef<Path>>(&self, target: P) -> Result<GitRepo> {
+        let shallow = self.sparse_paths.is_some()
+            || self.reference_repo.is_some()
+            || self.shallow_since.is_some();
+
+        let cloned = if shallow {
+            let cloned = self.git_clone_shallow(target.as_ref())?;
+
+            // Shallow clones check out the default branch, so switch to ours if needed
+            if let Some(b) = &self.branch {
+                if cloned.branch.as_deref() != Some(b.as_str()) {
+                    cli::run_git(Some(target.as_ref()), &["checkout", "--quiet", b])
+                        .wrap_err(format!("Failed to check out branch {}", b))?;
                 }
-                crate::GitCredentials::UserPassPlaintext { username, password } => {
-                    let mut cli_remote_url = self.url.clone();
-                    cli_remote_url.user = Some(username.to_string());
-                    cli_remote_url.token = Some(password.to_string());
-
-                    let shell_clone_command = if let Ok(spawn) = Command::new("git")
-                        .arg("clone")
-                        .arg(format!("{}", cli_remote_url))
-                        .arg(format!("{}", target.as_ref().display()))
-                        .arg("--no-single-branch")
-                        .arg("--depth=1")
-                        .stdout(Stdio::piped())
-                        .stderr(Stdio::null())
-                        .spawn()
-                    {
-                        spawn
-                    } else {
-                        return Err(eyre!("Failed to run git clone"));
-                    };
-
-                    let clone_out = if let Some(stdout) = shell_clone_command.stdout {
-                        stdout
-                    } else {
-                        return Err(eyre!("Failed to open stdout"));
-                    };
-
-                    // Re-create the GitCredentials
-                    let creds = GitCredentials::UserPassPlaintext { username, password };
-
-                    if let Ok(repo) = GitRepo::open(target.as_ref().to_path_buf(), None, None) {
-                        repo
-                    } else {
-                        return Err(eyre!("Failed to open shallow clone dir: {:?}", clone_out));
-                    }
-                    .with_credentials(Some(creds))
+            }
+
+            cloned
+        } else {
+            self.git_clone(target.as_ref())?
+        };
+
+        let commit_id = self.head.as_ref().map(|h| h.id.clone());
+
+        let repo = if cloned.is_shallow()? {
+            let repo = GitRepo::open(target.as_ref().to_path_buf(), self.branch.clone(), None)?;
+
+            if let Some(id) = &commit_id {
+                let tip = repo.head.as_ref().map(|h| h.id.as_str());
+
+                if tip != Some(id.as_str()) {
+                    return Err(eyre!(
+                        "Can't open shallow clone at commit {}, since it isn't the tip of the branch",
+                        id
+                    ));
                 }
             }
+
+            repo
         } else {
-            let parsed_uri = self.url.trim_auth();
-
-            info!("Url: {}", format!("{}", parsed_uri));
-            info!("Directory: {}", format!("{}", target.as_ref().display()));
-
-            let shell_clone_command = if let Ok(spawn) = Command::new("git")
-                .arg("clone")
-                .arg(format!("{}", parsed_uri))
-                .arg(format!("{}", target.as_ref().display()))
-                .arg("--no-single-branch")
-                .arg("--depth=1")
-                .stdout(Stdio::piped())
-                .stderr(Stdio::null())
-                .spawn()
+            GitRepo::open(
+                target.as_ref().to_path_buf(),
+                self.branch.clone(),
+                commit_id,
+            )?
+        };
+
+        let mut repo = repo.with_credentials(self.credentials.clone());
+        repo.credential_provider = self.credential_provider.clone();
+
+        Ok(repo)
+    }
+
+    /// Fetch only the commit `sha` into a new repo at `target`, and check it out in detached HEAD,
+    /// like `git fetch --depth=1 origin <sha>`. This avoids cloning any other history.
+    /// The server must allow fetching by commit id, e.g. with `uploadpack.allowAnySHA1InWant`.
+    /// Returns `Err` before fetching if a branch, tag, sparse paths, reference repo, no checkout,
+    /// object limit or shallow since date is set, since none of them apply to fetching one commit.
+    ///
+    /// *Note:* This requires `git` CLI to be installed
+    pub fn fetch_commit(&self, sha: &str, target: &Path) -> Result<GitRepo> {
+        UrlKind::require_remote(&self.url, "Fetching")?;
+
+        if self.max_objects.is_some() {
+            return Err(eyre!("Object limits are only supported by git_clone()"));
+        }
+
+        if self.shallow_since.is_some() {
+            return Err(eyre!(
+                "Shallow since a date is only supported by git_clone_shallow()"
+            ));
+        }
+
+        if self.branch.is_some() || self.tag.is_some() {
+            return Err(eyre!(
+                "Checking out a branch or tag isn't supported by fetch_commit(), which checks out the commit"
+            ));
+        }
+
+        if self.sparse_paths.is_some() {
+            return Err(eyre!("Sparse checkout isn't supported by fetch_commit()"));
+        }
+
+        if self.reference_repo.is_some() {
+            return Err(eyre!("Reference repos aren't supported by fetch_commit()"));
+        }
+
+        if self.no_checkout {
+            return Err(eyre!(
+                "No checkout isn't supported by fetch_commit(), which always checks out the commit"
+            ));
+        }
+
+        let (fetch_url, fetch_config) = cli::authenticated_url(
+            &self.url,
+            &self.credentials,
+            &self.credential_provider,
+            self.host_key_policy,
+            &self.proxy,
+        )?;
+
+        cli::run_git(None, &["init", "--quiet", &target.to_string_lossy()])
+            .wrap_err("Failed to init repo for fetch")?;
+
+        // Keep credentials out of the repo config, by only passing the authenticated url to fetch
+        cli::run_git(
+            Some(target),
+            &["remote", "add", "origin", &self.url.trim_auth().to_string()],
+        )?;
+
+        let mut fetch_command = cli::git_command(Some(target));
+        for config in fetch_config {
+            fetch_command.arg("-c").arg(config);
+        }
+
+        fetch_command
+            .arg("fetch")
+            .arg("--depth=1")
+            .arg(format!("{}", fetch_url))
+            .arg(sha);
+
+        match cli::run(&mut fetch_command) {
+            Ok(_) => {}
+            Err(e)
+                if e.to_string().contains("not our ref")
+                    || e.to_string().contains("unadvertised object") =>
             {
-                spawn
-            } else {
-                return Err(eyre!("Failed to run git clone"));
-            };
-
-            let clone_out = if let Ok(stdout) = shell_clone_command.wait_with_output() {
-                stdout
-            } else {
-                return Err(eyre!("Failed to wait for output"));
+                return Err(eyre!(
+                    "Server rejected fetching commit {} by id. It may need uploadpack.allowAnySHA1InWant enabled: {}",
+                    sha,
+                    e
+                ));
             }
-            .stdout;
+            Err(e) => return Err(e).wrap_err(format!("Failed to fetch commit {}", sha)),
+        }
 
-            if let Ok(repo) = GitRepo::open(target.as_ref().to_path_buf(), None, None) {
-                repo
-            } else {
-                return Err(eyre!("Failed to open shallow clone dir: {:?}", clone_out));
-            }
-        };
+        cli::run_git(
+            Some(target),
+            &["checkout", "--quiet", "--detach", "FETCH_HEAD"],
+        )
+        .wrap_err("Failed to check out fetched commit")?;
+
+        // There's no branch with an upstream in detached HEAD, so keep our url
+        let mut repo = GitRepo::open(target.to_path_buf(), None, None)?
+            .with_credentials(self.credentials.clone());
+        repo.url = self.url.trim_auth();
 
         Ok(repo)
     }
+
+    /// Check out `tag` in detached HEAD in the freshly cloned `repo`.
+    /// Tags that aren't reachable from a branch weren't fetched by the clone, so they're fetched first
+    fn checkout_tag(
+        repo: &Repository,
+        credential_config: &CredentialConfig,
+        proxy: &ProxyConfig,
+        tag: &str,
+        no_checkout: bool,
+    ) -> Result<()> {
+        let tag_ref = format!("refs/tags/{}", tag);
+
+        if repo.find_reference(&tag_ref).is_err() {
+            let mut fetch_options = git2::FetchOptions::new();
+            fetch_options.remote_callbacks(credential_config.callbacks()?);
+            if let Some(proxy) = proxy.to_git2() {
+                fetch_options.proxy_options(proxy);
+            }
+
+            repo.find_remote("origin")?
+                .fetch(
+                    &[format!("+{}:{}", tag_ref, tag_ref)],
+                    Some(&mut fetch_options),
+                    None,
+                )
+                .wrap_err(format!("Tag not found: {}", tag))?;
+        }
+
+        let commit = repo
+            .revparse_single(&tag_ref)
+            .wrap_err(format!("Tag not found: {}", tag))?
+            .peel_to_commit()
+            .wrap_err(format!("Tag does not point at a commit: {}", tag))?;
+
+        if !no_checkout {
+            let mut checkout = git2::build::CheckoutBuilder::new();
+            checkout.force();
+
+            repo.checkout_tree(commit.as_object(), Some(&mut checkout))?;
+        }
+        repo.set_head_detached(commit.id())?;
+
+        Ok(())
+    }
+
+    /// Check out `commit_id` in detached HEAD in the freshly cloned `repo`, which has `branch` checked out.
+    /// Returns `Err` if the commit isn't reachable from the branch
+    fn checkout_commit_on_branch(
+        repo: &Repository,
+        branch: &str,
+        commit_id: &str,
+        no_checkout: bool,
+    ) -> Result<()> {
+        let branch_head = repo.head()?.peel_to_commit()?;
+
+        let commit = repo
+            .find_commit(git2::Oid::from_str(commit_id)?)
+            .wrap_err(format!("Commit {} not found in clone", commit_id))?;
+
+        if commit.id() != branch_head.id()
+            && !repo.graph_descendant_of(branch_head.id(), commit.id())?
+        {
+            return Err(eyre!(
+                "Commit {} is not reachable from branch {}",
+                commit_id,
+                branch
+            ));
+        }
+
+        if !no_checkout {
+            let mut checkout = git2::build::CheckoutBuilder::new();
+            checkout.force();
+
+            repo.checkout_tree(commit.as_object(), Some(&mut checkout))?;
+        }
+        repo.set_head_detached(commit.id())?;
+
+        Ok(())
+    }
+
+    /// Configure cone-mode sparse checkout of `paths` in the freshly cloned `repo_dir`, then check out
+    fn sparse_checkout(repo_dir: &Path, paths: &[String]) -> Result<()> {
+        cli::run_git(Some(repo_dir), &["sparse-checkout", "init", "--cone"])
+            .wrap_err("Failed to initialize sparse checkout. Sparse checkout requires git 2.25+")?;
+
+        let mut set_command = cli::git_command(Some(repo_dir));
+        set_command.arg("sparse-checkout").arg("set").args(paths);
+        cli::run(&mut set_command).wrap_err("Failed to set sparse checkout paths")?;
+
+        cli::run_git(Some(repo_dir), &["checkout"]).wrap_err("Failed to checkout sparse paths")?;
+
+        Ok(())
+    }
 }
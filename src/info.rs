@@ -1,14 +1,29 @@
+use crate::cli;
 use crate::{
-    BranchHeads, GitCommitMeta, GitCredentials, GitRepo, GitRepoCloneRequest, GitRepoInfo,
+    BlobInfo, BranchHeads, BranchHeadsDiff, CommitChangeSummary, CommitCount, CredentialConfig,
+    CredentialProvider, Delta, DiffStats, GitCommitMeta, GitCredentials, GitDiffOptions,
+    GitMetaError, GitRepo, GitRepoCloneRequest, GitRepoInfo, GitRepoMut, GitSignature,
+    HostKeyPolicy, MergeAnalysis, PrRefKind, ProxyConfig, PullRequestRef, RangeStats, RefMeta,
+    RemoteRef, RepoSize, RepoState, SharedCredentialProvider, SubmoduleStatus, TagMeta, TreeEntry,
+    TreeEntryKind, UrlKind,
 };
 
 use std::collections::HashMap;
-use std::path::{Path, PathBuf};
+use std::io::{Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
 
+use chrono::prelude::*;
 use color_eyre::eyre::{eyre, Context, ContextCompat, Result};
-use git2::{Branch, BranchType, Commit, Cred, Oid, Repository};
+use git2::{Branch, BranchType, Commit, ErrorCode, Oid, Repository};
+use git_url_parse::{GitUrl, Scheme};
 use mktemp::Temp;
-use tracing::debug;
+use tracing::{debug, instrument, warn};
+
+/// The notes ref that `GitRepoMut::set_commit_metadata()` writes to, kept apart from `refs/notes/commits`
+pub(crate) const COMMIT_METADATA_NOTES_REF: &str = "refs/notes/git-meta";
 
 impl GitRepoInfo {
     pub fn to_repo(&self) -> GitRepo {
@@ -19,39 +34,117 @@ impl GitRepoInfo {
         self.into()
     }
 
+    /// Returns whether `self.url` is a remote url, or a local path.
+    /// Operations that clone or fetch require a `Remote` url
+    pub fn url_kind(&self) -> UrlKind {
+        UrlKind::from(&self.url)
+    }
+
+    /// Set the directory where temporary scratch clones are created,
+    /// such as by `get_remote_branch_head_refs()` and `new_commits_exist()`.
+    /// Defaults to the system temp directory.
+    ///
+    /// Scratch clones are removed when the operation returns, but not if the process is killed.
+    /// Pointing this at a tmpfs ensures leftovers don't outlive a reboot.
+    pub fn with_temp_dir(mut self, dir: PathBuf) -> Self {
+        self.temp_dir = Some(dir);
+        self
+    }
+
+    /// Give up listing remote refs with `get_remote_branch_head_refs()` after `timeout`,
+    /// returning `GitMetaError::ConnectTimeout`. This prevents hanging on an unresponsive remote.
+    /// Only connecting and listing are timed, not the scratch clone made when no `path` is set
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Set how the host key of an ssh remote is verified. Defaults to `HostKeyPolicy::Strict`.
+    /// CI without a populated `known_hosts` can opt into `HostKeyPolicy::AcceptNew`, for operations that use the `git` CLI
+    pub fn with_host_key_check(mut self, policy: HostKeyPolicy) -> Self {
+        self.host_key_policy = policy;
+        self
+    }
+
+    /// Set the proxy to connect through, for both `git2` and the `git` CLI. Defaults to `ProxyConfig::None`
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Create a new temporary directory for a scratch clone, within `self.temp_dir` if set
+    fn new_scratch_dir(&self) -> Result<Temp> {
+        let temp_dir = if let Some(dir) = &self.temp_dir {
+            Temp::new_dir_in(dir)
+        } else {
+            Temp::new_dir()
+        };
+
+        temp_dir.wrap_err("Unable to create temp directory")
+    }
+
     /// Return the remote name from the given `git2::Repository`
     /// For example, the typical remote name: `origin`
     pub fn get_remote_name(&self, r: &git2::Repository) -> Result<String> {
+        Ok(utf8_lossy(
+            &self.get_remote_name_bytes(r)?,
+            "Upstream remote",
+        ))
+    }
+
+    /// Like `get_remote_name()`, but returns the exact bytes of the remote name, even if not valid utf-8.
+    /// The checked out branch's name doesn't need to be valid utf-8 either
+    pub fn get_remote_name_bytes(&self, r: &git2::Repository) -> Result<Vec<u8>> {
         let local_branch = r.head().and_then(|h| h.resolve())?;
-        let local_branch = local_branch.name();
 
-        if let Some(refname) = local_branch {
-            let upstream_remote = r.branch_upstream_remote(refname)?;
+        if let Ok(refname) = std::str::from_utf8(local_branch.name_bytes()) {
+            return Ok(r.branch_upstream_remote(refname)?.to_vec());
+        }
 
-            if let Some(name) = upstream_remote.as_str() {
-                Ok(name.to_string())
-            } else {
-                Err(eyre!("Upstream remote name not valid utf-8"))
+        // git2 only looks up `branch.<name>.remote` by a `&str` name, so match the raw config names instead
+        let branch = local_branch
+            .name_bytes()
+            .strip_prefix(b"refs/heads/")
+            .wrap_err("HEAD is not a local branch")?;
+        let key = [b"branch.", branch, b".remote"].concat();
+
+        let config = r.config()?.snapshot()?;
+        let mut upstream_remote = None;
+
+        // Like git, the last value wins
+        let entries = config.entries(Some(r"^branch\..*\.remote$"))?;
+        for entry in &entries {
+            let entry = entry?;
+            if entry.name_bytes() == key.as_slice() {
+                upstream_remote = Some(entry.value_bytes().to_vec());
             }
-        } else {
-            Err(eyre!("Local branch name not valid utf-8"))
         }
+
+        upstream_remote.wrap_err(format!(
+            "Branch {} has no upstream remote",
+            utf8_lossy(branch, "Local branch")
+        ))
     }
 
     /// Return a `HashMap<String, GitCommitMeta>` for a branch containing
     /// the branch names and the latest commit of the branch`.
-    /// Providing a `branch_filter` will only return branches based on
-    /// patterns matching the start of the branch name.
+    /// Providing a `branch_filter` will only return the branches it names.
+    /// If a `connect_timeout` is set, returns `GitMetaError::ConnectTimeout` if the listing doesn't finish in time
+    #[instrument(
+        skip_all,
+        fields(
+            url = %self.url.trim_auth(),
+            branch = ?self.branch,
+            branch_filter = ?branch_filter
+        )
+    )]
     pub fn get_remote_branch_head_refs(
         &self,
         branch_filter: Option<Vec<String>>,
     ) -> Result<BranchHeads> {
-        // Create a temp directory (In case we need to clone)
-        let temp_dir = if let Ok(temp_dir) = Temp::new_dir() {
-            temp_dir
-        } else {
-            return Err(eyre!("Unable to create temp directory"));
-        };
+        // The scratch clone (if we need one) is removed when this guard is dropped.
+        // It lives until we return, so cleanup happens on error paths too.
+        let mut scratch_dir = None;
 
         // Check on path. If it doesn't exist, then we gotta clone and open the repo
         // so we can have a git2::Repository to work with
@@ -59,6 +152,7 @@ impl GitRepoInfo {
             GitRepo::to_repository_from_path(p)?
         } else {
             // Shallow clone
+            let temp_dir = scratch_dir.insert(self.new_scratch_dir()?);
 
             let clone: GitRepoCloneRequest = self.into();
             clone
@@ -66,15 +160,13 @@ impl GitRepoInfo {
                 .to_repository()?
         };
 
-        let cb = self.build_git2_remotecallback();
-
         let remote_name = if let Ok(name) = self.get_remote_name(&repo) {
             name
         } else {
             return Err(eyre!("Could not read remote name from git2::Repository"));
         };
 
-        let mut remote = if let Ok(r) = repo.find_remote(&remote_name) {
+        let remote = if let Ok(r) = repo.find_remote(&remote_name) {
             r
         } else if let Ok(anon_remote) = repo.remote_anonymous(&remote_name) {
             anon_remote
@@ -85,42 +177,61 @@ impl GitRepoInfo {
             ));
         };
 
-        // Connect to the remote and call the printing function for each of the
-        // remote references.
-        let connection =
-            if let Ok(conn) = remote.connect_auth(git2::Direction::Fetch, Some(cb?), None) {
-                conn
-            } else {
-                return Err(eyre!("Unable to connect to git repo"));
-            };
+        let url = remote
+            .url()
+            .wrap_err("Remote url is not valid utf-8")?
+            .to_string();
+
+        let remote_heads = if let Some(timeout) = self.connect_timeout {
+            // git2 has no connect timeout, so list on another thread and stop waiting at the deadline.
+            // git2 can't cancel a connection either, so after a timeout the thread is left to finish
+            // on its own, once the remote responds or drops the connection.
+            // It only holds a detached remote, so nothing is left behind on disk
+            let (sender, receiver) = mpsc::channel();
+            let credential_config = self.credential_config();
+            let proxy = self.proxy.clone();
+
+            thread::spawn(move || {
+                let _ = sender.send(list_remote_heads(&url, &credential_config, &proxy));
+            });
+
+            match receiver.recv_timeout(timeout) {
+                Ok(heads) => heads?,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    return Err(GitMetaError::ConnectTimeout {
+                        url: self.url.trim_auth().to_string(),
+                        timeout,
+                    }
+                    .into())
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err(eyre!("Listing remote refs stopped before it finished"))
+                }
+            }
+        } else {
+            list_remote_heads(&url, &self.credential_config(), &self.proxy)?
+        };
 
         let git_branch_ref_prefix = "refs/heads/";
         let mut ref_map: HashMap<String, GitCommitMeta> = HashMap::new();
 
-        for git_ref in connection
-            .list()?
+        for (name, oid) in remote_heads
             .iter()
-            .filter(|head| head.name().starts_with(git_branch_ref_prefix))
+            .filter(|(name, _)| name.starts_with(git_branch_ref_prefix))
         {
-            let branch_name = git_ref
-                .name()
-                .to_string()
-                .rsplit(git_branch_ref_prefix)
-                .collect::<Vec<&str>>()[0]
-                .to_string();
+            let branch_name =
+                name.rsplit(git_branch_ref_prefix).collect::<Vec<&str>>()[0].to_string();
 
             if let Some(ref branches) = branch_filter {
-                if branches.contains(&branch_name.to_string()) {
+                if !branches.contains(&branch_name) {
                     continue;
                 }
             }
 
-            // Get the commit object
-            let commit = repo.find_commit(git_ref.oid())?;
+            // Get the commit object. Peel, in case the ref points at an annotated tag
+            let commit = repo.find_object(*oid, None)?.peel_to_commit()?;
 
-            let head_commit = GitCommitMeta::new(commit.id().as_bytes())
-                .with_timestamp(commit.time().seconds())
-                .with_message(commit.message().map(|m| m.to_string()));
+            let head_commit = GitCommitMeta::from(&commit);
 
             ref_map.insert(branch_name, head_commit);
         }
@@ -128,12 +239,171 @@ impl GitRepoInfo {
         Ok(ref_map)
     }
 
+    /// Returns the branch heads advertised by the remote at `self.url`, without cloning or opening a repo.
+    /// Unlike `get_remote_branch_head_refs()`, this never opens the repo at `self.path`, even if it's set,
+    /// so only `insteadOf` rewrites from the global config are applied.
+    ///
+    /// Commits aren't fetched, so each `GitCommitMeta` only has an `id`, without a message or timestamp.
+    /// Branches that point at a tag object are given the id of the tag object
+    pub fn remote_branches(&self) -> Result<BranchHeads> {
+        let rewrites = Self::url_rewrites_in(&git2::Config::open_default()?)?;
+        let url = Self::apply_url_rewrites(self.url.to_string(), rewrites);
+        let mut remote = git2::Remote::create_detached(&url)?;
+
+        let cb = self.build_git2_remotecallback()?;
+
+        let connection = remote
+            .connect_auth(git2::Direction::Fetch, Some(cb), self.proxy.to_git2())
+            .wrap_err("Unable to connect to git repo")?;
+
+        Ok(connection
+            .list()?
+            .iter()
+            .filter_map(|head| {
+                head.name()
+                    .strip_prefix("refs/heads/")
+                    .map(|branch| (branch.to_string(), GitCommitMeta::new(head.oid())))
+            })
+            .collect())
+    }
+
+    /// Returns every ref advertised by the remote, like `git ls-remote`, including tags and
+    /// refs outside of `refs/heads/` (e.g. `refs/pull/*`).
+    /// The remote `HEAD` reports its `symref_target`, which is the remote's default branch.
+    /// Connects to the remote configured in the repo at `self.path`, or to `self.url` if no path is set
+    pub fn remote_refs(&self) -> Result<Vec<RemoteRef>> {
+        // Without a repo on disk, a detached remote lets us list refs without cloning
+        let repo;
+        let url = self.rewritten_url()?;
+        let mut remote = if let Some(p) = self.path.clone() {
+            repo = GitRepo::to_repository_from_path(p)?;
+            let remote_name = self.get_remote_name(&repo)?;
+            repo.find_remote(&remote_name)?
+        } else {
+            git2::Remote::create_detached(&url)?
+        };
+
+        let cb = self.build_git2_remotecallback()?;
+
+        let connection = remote
+            .connect_auth(git2::Direction::Fetch, Some(cb), self.proxy.to_git2())
+            .wrap_err("Unable to connect to git repo")?;
+
+        Ok(connection
+            .list()?
+            .iter()
+            .map(|head| RemoteRef {
+                name: head.name().to_string(),
+                oid: head.oid().to_string(),
+                symref_target: head.symref_target().map(str::to_string),
+            })
+            .collect())
+    }
+
+    /// Returns the pull request heads advertised by the remote, from GitHub's `refs/pull/<number>/head`
+    /// and GitLab's `refs/merge-requests/<number>/head`, ordered by number. This finds PR branches to
+    /// check out without a platform API token. Connects to the remote the same way as `remote_refs()`
+    pub fn list_pull_request_refs(&self) -> Result<Vec<PullRequestRef>> {
+        let mut pull_requests: Vec<PullRequestRef> = self
+            .remote_refs()?
+            .into_iter()
+            .filter_map(|remote_ref| {
+                let (kind, rest) = if let Some(rest) = remote_ref.name.strip_prefix("refs/pull/") {
+                    (PrRefKind::GitHub, rest)
+                } else if let Some(rest) = remote_ref.name.strip_prefix("refs/merge-requests/") {
+                    (PrRefKind::GitLab, rest)
+                } else {
+                    return None;
+                };
+
+                let number = rest.strip_suffix("/head")?.parse().ok()?;
+
+                Some(PullRequestRef {
+                    number,
+                    kind,
+                    oid: remote_ref.oid,
+                })
+            })
+            .collect();
+
+        pull_requests.sort_by_key(|pull_request| pull_request.number);
+
+        Ok(pull_requests)
+    }
+
+    /// Returns the url rewrites from `url.<base>.insteadOf` config, as `(instead_of, base)` pairs.
+    /// Urls starting with `instead_of` are rewritten to start with `base`, like git does for fetches.
+    /// Reads the config of the repo at `self.path`, or the global config if no path is set.
+    /// Files from `include.path` are followed
+    pub fn url_rewrites(&self) -> Result<Vec<(String, String)>> {
+        let config = if let Some(p) = self.path.clone() {
+            GitRepo::to_repository_from_path(p)?.config()?
+        } else {
+            git2::Config::open_default()?
+        };
+
+        Self::url_rewrites_in(&config)
+    }
+
+    /// Returns the url rewrites from `url.<base>.insteadOf` entries of `config`, as `(instead_of, base)` pairs
+    fn url_rewrites_in(config: &git2::Config) -> Result<Vec<(String, String)>> {
+        let mut rewrites = Vec::new();
+
+        // Config names are lowercased, except for the `<base>` subsection
+        let entries = config.entries(Some(r"^url\..*\.insteadof$"))?;
+        for entry in &entries {
+            let entry = entry?;
+
+            let base = entry
+                .name()
+                .and_then(|n| n.strip_prefix("url."))
+                .and_then(|n| n.strip_suffix(".insteadof"));
+
+            if let (Some(base), Some(instead_of)) = (base, entry.value()) {
+                rewrites.push((instead_of.to_string(), base.to_string()));
+            }
+        }
+
+        Ok(rewrites)
+    }
+
+    /// Returns `self.url` with `url_rewrites()` applied. Like git, the longest matching prefix wins
+    pub(crate) fn rewritten_url(&self) -> Result<String> {
+        Ok(Self::apply_url_rewrites(
+            self.url.to_string(),
+            self.url_rewrites()?,
+        ))
+    }
+
+    /// Returns `url` with the longest matching prefix in `rewrites` replaced
+    fn apply_url_rewrites(url: String, rewrites: Vec<(String, String)>) -> String {
+        let rewrite = rewrites
+            .into_iter()
+            .filter(|(instead_of, _)| url.starts_with(instead_of.as_str()))
+            .max_by_key(|(instead_of, _)| instead_of.len());
+
+        match rewrite {
+            Some((instead_of, base)) => format!("{}{}", base, &url[instead_of.len()..]),
+            None => url,
+        }
+    }
+
+    /// Compare the remote branch heads of `self` and `other` with `get_remote_branch_head_refs()`.
+    /// Useful for verifying that a mirror is in sync with its upstream.
+    /// `branches` is passed through to `get_remote_branch_head_refs()` for both repos, so only those branches are compared
+    pub fn compare_heads(
+        &self,
+        other: &GitRepoInfo,
+        branches: Option<Vec<String>>,
+    ) -> Result<BranchHeadsDiff> {
+        let ours = self.get_remote_branch_head_refs(branches.clone())?;
+        let theirs = other.get_remote_branch_head_refs(branches)?;
+
+        Ok(BranchHeadsDiff::between(&ours, &theirs))
+    }
+
     /// Returns a `bool` if a commit exists in the branch using the `git2` crate
-    pub fn is_commit_in_branch<'repo>(
-        r: &'repo Repository,
-        commit: &Commit,
-        branch: &Branch,
-    ) -> Result<bool> {
+    pub fn is_commit_in_branch(r: &Repository, commit: &Commit, branch: &Branch) -> Result<bool> {
         let branch_head = branch.get().peel_to_commit();
 
         if branch_head.is_err() {
@@ -157,6 +427,68 @@ impl GitRepoInfo {
         check_commit_in_branch.wrap_err("Unable to determine if commit exists within branch")
     }
 
+    /// Returns `true` if the HEAD of `branch` is reachable from the HEAD of `into`, like the check
+    /// `git branch -d` makes before deleting a branch. Use this to avoid deleting unmerged work.
+    /// Both are branch names, local (e.g. `feature`) or remote (e.g. `origin/main`)
+    pub fn is_branch_merged<S: AsRef<str>>(&self, branch: S, into: S) -> Result<bool> {
+        let repo = self.to_repo().to_repository()?;
+
+        let find_branch = |name: &str| {
+            repo.find_branch(name, BranchType::Local)
+                .or_else(|_| repo.find_branch(name, BranchType::Remote))
+                .wrap_err(format!("Branch not found: {}", name))
+        };
+
+        let branch_head = find_branch(branch.as_ref())?
+            .get()
+            .peel_to_commit()
+            .wrap_err("Unable to extract branch HEAD commit")?;
+
+        let into_branch = find_branch(into.as_ref())?;
+
+        Self::is_commit_in_branch(&repo, &branch_head, &into_branch)
+    }
+
+    /// Returns the names of the branches of `kind` that contain `commit`, like `git branch --contains <commit>`.
+    /// Useful for finding where a fix landed. `commit` can be anything `git rev-parse` accepts.
+    /// Symbolic refs like `origin/HEAD` are skipped, since they would repeat the branch they point at
+    pub fn branches_containing<S: AsRef<str>>(
+        &self,
+        commit: S,
+        kind: BranchType,
+    ) -> Result<Vec<String>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let commit = repo
+            .revparse_single(commit.as_ref())
+            .and_then(|object| object.peel_to_commit())
+            .wrap_err(format!("Could not resolve {}", commit.as_ref()))?;
+
+        let mut names = Vec::new();
+
+        for branch in repo.branches(Some(kind))? {
+            let (branch, _) = branch?;
+
+            if branch.get().kind() == Some(git2::ReferenceType::Symbolic) {
+                continue;
+            }
+
+            if !Self::is_commit_in_branch(&repo, &commit, &branch)? {
+                continue;
+            }
+
+            match branch.name()? {
+                Some(name) => names.push(name.to_string()),
+                None => warn!(
+                    "Skipping branch with name not valid utf-8: {:?}",
+                    String::from_utf8_lossy(branch.name_bytes()?)
+                ),
+            }
+        }
+
+        Ok(names)
+    }
+
     /// Return the `git2::Branch` struct for a local repo (as opposed to a remote repo)
     /// If `local_branch` is not provided, we'll select the current active branch, based on HEAD
     pub fn get_git2_branch<'repo>(
@@ -176,10 +508,15 @@ impl GitRepoInfo {
             }
             None => {
                 // Getting the HEAD of the current
-                let head = r.head();
+                let head = match r.head() {
+                    Ok(head) => head,
+                    // A freshly initialized repo has no commits for HEAD to point at
+                    Err(e) if e.code() == ErrorCode::UnbornBranch => return Ok(None),
+                    Err(e) => return Err(e.into()),
+                };
 
                 // Find the current local branch...
-                let local_branch = Branch::wrap(head?);
+                let local_branch = Branch::wrap(head);
 
                 debug!("Returning HEAD branch: {:?}", local_branch.name()?);
 
@@ -202,6 +539,119 @@ impl GitRepoInfo {
         }
     }
 
+    /// Returns the `git2::Commit` that `meta` refers to in the already opened repo `r`.
+    /// The commit borrows from `r`, so `r` is passed in rather than opened from `self.path`.
+    /// Returns `Err` if the commit isn't in `r`, which can happen after a shallow clone
+    pub fn find_commit<'repo>(r: &'repo Repository, meta: &GitCommitMeta) -> Result<Commit<'repo>> {
+        let oid = Oid::from_str(&meta.id).wrap_err(format!("Invalid commit id: {}", meta.id))?;
+
+        match r.find_commit(oid) {
+            Ok(commit) => Ok(commit),
+            Err(e) if e.code() == ErrorCode::NotFound => {
+                let hint = if r.is_shallow() {
+                    ". The repo is a shallow clone, so it may be outside of the fetched history"
+                } else {
+                    ""
+                };
+
+                Err(eyre!("Commit {} not found in repo{}", meta.id, hint))
+            }
+            Err(e) => Err(e).wrap_err(format!("Failed to find commit {}", meta.id)),
+        }
+    }
+
+    /// Returns the short name of the remote-tracking branch of `local` (e.g. `origin/main`),
+    /// the same way `GitRepo::open()` resolves a branch.
+    /// Returns `None` if `local` has no upstream, and `Err` if `local` is not a local branch
+    pub fn upstream_branch(&self, local: &str) -> Result<Option<String>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let branch = Self::get_git2_branch(&repo, &Some(local.to_string()))?
+            .wrap_err(format!("Local branch not found: {}", local))?;
+
+        let upstream = match branch.upstream() {
+            Ok(upstream) => upstream,
+            Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let name = upstream
+            .name()?
+            .wrap_err("Upstream branch name not valid utf-8")?;
+
+        Ok(Some(name.to_string()))
+    }
+
+    /// Returns the short name of the remote branch that `local` pushes to (e.g. `fork/main`), like `git rev-parse local@{push}`.
+    /// The push remote is read from `branch.<local>.pushRemote` or `remote.pushDefault`, for triangular
+    /// workflows that fetch from one remote and push to another. Falls back to `upstream_branch()` when neither is set
+    pub fn push_upstream_branch(&self, local: &str) -> Result<Option<String>> {
+        let repo = self.to_repo().to_repository()?;
+
+        if Self::get_git2_branch(&repo, &Some(local.to_string()))?.is_none() {
+            return Err(eyre!("Local branch not found: {}", local));
+        }
+
+        let config = repo.config()?.snapshot()?;
+
+        let push_remote = config
+            .get_string(&format!("branch.{}.pushRemote", local))
+            .or_else(|_| config.get_string("remote.pushDefault"));
+
+        let push_remote = if let Ok(remote) = push_remote {
+            remote
+        } else {
+            return self.upstream_branch(local);
+        };
+
+        // Only `push.default=upstream` pushes to the merge branch. Otherwise, a triangular push uses the same name
+        let remote_branch = match config.get_str("push.default") {
+            Ok("upstream") => config
+                .get_string(&format!("branch.{}.merge", local))
+                .ok()
+                .and_then(|merge| merge.strip_prefix("refs/heads/").map(str::to_string)),
+            _ => Some(local.to_string()),
+        };
+
+        Ok(remote_branch.map(|branch| format!("{}/{}", push_remote, branch)))
+    }
+
+    /// Returns the name of the default branch, without hardcoding `main` or `master`.
+    /// The remote's symbolic HEAD (e.g. `origin/HEAD`) is checked first, as set by clone or `git remote set-head`,
+    /// and used if its branch exists locally.
+    /// Otherwise, the first of `candidates` that exists as a local branch is returned. An empty `candidates`
+    /// defaults to `["main", "master"]`. Returns `None` if neither finds a branch
+    pub fn resolve_default_branch(&self, candidates: &[&str]) -> Result<Option<String>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let remote_name =
+            Self::remote_name_from_repository(&repo)?.unwrap_or_else(|| "origin".to_string());
+        let remote_prefix = format!("refs/remotes/{}/", remote_name);
+
+        if let Ok(remote_head) = repo.find_reference(&format!("{}HEAD", remote_prefix)) {
+            if let Some(branch) = remote_head
+                .symbolic_target()
+                .and_then(|target| target.strip_prefix(remote_prefix.as_str()))
+            {
+                // e.g. `git clone --single-branch -b dev` has no local branch for the remote HEAD
+                if repo.find_branch(branch, BranchType::Local).is_ok() {
+                    return Ok(Some(branch.to_string()));
+                }
+            }
+        }
+
+        let candidates = if candidates.is_empty() {
+            &["main", "master"][..]
+        } else {
+            candidates
+        };
+
+        Ok(candidates
+            .iter()
+            .find(|name| repo.find_branch(name, BranchType::Local).is_ok())
+            .map(|name| name.to_string()))
+    }
+
     /// Return the remote url from the given Repository
     ///
     /// Returns `None` if current branch is local only
@@ -224,27 +674,18 @@ impl GitRepoInfo {
 
     /// Return the remote name from the given Repository
     fn remote_name_from_repository(r: &Repository) -> Result<Option<String>> {
-        let local_branch = r.head().and_then(|h| h.resolve())?;
-        let local_branch_name = if let Some(name) = local_branch.name() {
-            name
-        } else {
-            return Err(eyre!("Local branch name is not valid utf-8"));
+        let local_branch = match r.head().and_then(|h| h.resolve()) {
+            Ok(branch) => branch,
+            // A branch with no commits yet has no upstream to push to
+            Err(e) if e.code() == ErrorCode::UnbornBranch => return Ok(None),
+            Err(e) => return Err(e.into()),
         };
+        let local_branch_name = utf8_lossy(local_branch.name_bytes(), "Local branch");
 
-        let upstream_remote_name_buf =
-            if let Ok(remote) = r.branch_upstream_remote(local_branch_name) {
-                Some(remote)
-            } else {
-                //return Err(eyre!("Could not retrieve remote name from local branch"));
-                None
-            };
+        let upstream_remote_name_buf = r.branch_upstream_remote(&local_branch_name).ok();
 
         if let Some(remote) = upstream_remote_name_buf {
-            let remote_name = if let Some(name) = remote.as_str() {
-                Some(name.to_string())
-            } else {
-                return Err(eyre!("Remote name not valid utf-8"));
-            };
+            let remote_name = Some(utf8_lossy(&remote, "Remote"));
 
             debug!("Remote name: {:?}", &remote_name);
 
@@ -265,174 +706,1947 @@ impl GitRepoInfo {
         GitRepoInfo::remote_url_from_repository(local_repo)
     }
 
-    /// Returns a `Result<Option<Vec<PathBuf>>>` containing files changed between `commit1` and `commit2`
+    /// Returns a `Result<Option<Vec<PathBuf>>>` containing files changed between `commit1` and `commit2`.
+    /// If the trees of both commits are identical (like diffing a commit against itself), returns `Ok(Some(vec![]))`.
+    /// Errors resolving either commit, or computing the diff, are returned as `Err`
     pub fn list_files_changed_between<S: AsRef<str>>(
         &self,
         commit1: S,
         commit2: S,
     ) -> Result<Option<Vec<PathBuf>>> {
-        let repo = self.to_repo();
+        self.list_files_changed_between_with(commit1, commit2, &GitDiffOptions::default())
+    }
 
-        let commit1 = self.expand_partial_commit_id(commit1.as_ref())?;
-        let commit2 = self.expand_partial_commit_id(commit2.as_ref())?;
+    /// Like `list_files_changed_between()`, but only paths matching `options.pathspec`
+    /// and not matching `options.exclude` are listed
+    #[instrument(
+        skip_all,
+        fields(
+            url = %self.url.trim_auth(),
+            commit1 = commit1.as_ref(),
+            commit2 = commit2.as_ref()
+        )
+    )]
+    pub fn list_files_changed_between_with<S: AsRef<str>>(
+        &self,
+        commit1: S,
+        commit2: S,
+        options: &GitDiffOptions,
+    ) -> Result<Option<Vec<PathBuf>>> {
+        let repo = self.to_repo().to_repository()?;
+
+        Self::files_changed_between_from_repository(
+            &repo,
+            commit1.as_ref(),
+            commit2.as_ref(),
+            options,
+        )
+    }
 
-        let repo = repo.to_repository()?;
+    /// Like `list_files_changed_between()`, but returns the changed files as an iterator, along with how each changed.
+    /// The whole diff is still computed up front on another thread, which then hands over a few files at a time.
+    /// Dropping the iterator early only skips converting the rest, so a large diff costs as much time and memory
+    /// as `list_files_changed_between()`. Bad commit ids are returned as `Err` from this call, not from the iterator
+    pub fn changed_files_iter<S: AsRef<str>>(
+        &self,
+        commit1: S,
+        commit2: S,
+    ) -> Result<impl Iterator<Item = Result<(PathBuf, Delta)>>> {
+        let commit1 = commit1.as_ref().to_string();
+        let commit2 = commit2.as_ref().to_string();
 
-        let oid1 = Oid::from_str(&commit1)?;
-        let oid2 = Oid::from_str(&commit2)?;
+        {
+            let repo = self.to_repo().to_repository()?;
+            Self::commit_from_repository(&repo, &commit1)?;
+            Self::commit_from_repository(&repo, &commit2)?;
+        }
 
-        let git2_commit1 = repo.find_commit(oid1)?.tree()?;
-        let git2_commit2 = repo.find_commit(oid2)?.tree()?;
+        // Bounded, so the walk only gets a little ahead of the caller
+        let (sender, receiver) = mpsc::sync_channel(64);
+        let info = self.clone();
 
-        let diff = repo.diff_tree_to_tree(Some(&git2_commit1), Some(&git2_commit2), None)?;
+        thread::spawn(move || {
+            let send_changes = || -> Result<()> {
+                let repo = info.to_repo().to_repository()?;
 
-        let mut paths = Vec::new();
+                let tree1 = Self::commit_from_repository(&repo, &commit1)?.tree()?;
+                let tree2 = Self::commit_from_repository(&repo, &commit2)?.tree()?;
 
-        diff.print(git2::DiffFormat::NameOnly, |delta, _hunk, _line| {
-            let delta_path = if let Some(p) = delta.new_file().path() {
-                p
-            } else {
-                return false;
-            };
+                let diff = repo.diff_tree_to_tree(Some(&tree1), Some(&tree2), None)?;
 
-            paths.push(delta_path.to_path_buf());
-            true
-        })
-        .wrap_err("File path not found in new commit to compare")?;
+                for delta in diff.deltas() {
+                    let path = delta.new_file().path().or_else(|| delta.old_file().path());
 
-        if !paths.is_empty() {
-            return Ok(Some(paths));
-        }
+                    if let Some(path) = path {
+                        // The receiver was dropped, so nobody wants the rest
+                        if sender
+                            .send(Ok((path.to_path_buf(), delta.status().into())))
+                            .is_err()
+                        {
+                            return Ok(());
+                        }
+                    }
+                }
 
-        Ok(None)
-    }
+                Ok(())
+            };
 
-    /// Returns a `Result<Option<Vec<PathBuf>>>` containing files changed between `commit` and `commit~1` (the previous commit)
-    pub fn list_files_changed_at<S: AsRef<str>>(&self, commit: S) -> Result<Option<Vec<PathBuf>>> {
-        let repo = self.to_repo();
+            if let Err(e) = send_changes() {
+                let _ = sender.send(Err(e));
+            }
+        });
 
-        let commit = self.expand_partial_commit_id(commit.as_ref())?;
+        Ok(receiver.into_iter())
+    }
 
-        let git2_repo = repo.to_repository()?;
+    /// Parse a commit range string like `a..b` or `a...b`, as accepted by the git CLI.
+    /// Each endpoint can be anything `git rev-parse` accepts, like a commit id, branch or tag.
+    /// An empty endpoint defaults to `HEAD`, so `a..` is the same as `a..HEAD`.
+    /// Returns the resolved endpoints, and `true` if the range was symmetric (`...`)
+    pub fn parse_range(&self, range: &str) -> Result<(GitCommitMeta, GitCommitMeta, bool)> {
+        let repo = self.to_repo().to_repository()?;
 
-        let oid = Oid::from_str(&commit)?;
-        let git2_commit = git2_repo.find_commit(oid)?;
+        let (from, to, symmetric) = Self::range_from_repository(&repo, range)?;
 
-        let mut changed_files = Vec::new();
+        Ok((
+            GitCommitMeta::from(&from),
+            GitCommitMeta::from(&to),
+            symmetric,
+        ))
+    }
 
-        for parent in git2_commit.parents() {
-            let parent_commit_id = hex::encode(parent.id().as_bytes());
+    /// Returns a `Result<Option<Vec<PathBuf>>>` containing files changed in a commit range, like `a..b` or `a...b`.
+    /// Like `git diff`, a symmetric range `a...b` lists the files changed in `b` since the merge base of `a` and `b`.
+    /// See `parse_range()` for the accepted syntax
+    pub fn files_changed_in_range(&self, range: &str) -> Result<Option<Vec<PathBuf>>> {
+        let repo = self.to_repo().to_repository()?;
 
-            if let Some(path_vec) = self.list_files_changed_between(&parent_commit_id, &commit)? {
-                for p in path_vec {
-                    changed_files.push(p);
-                }
-            }
-        }
+        let (from, to, symmetric) = Self::range_from_repository(&repo, range)?;
 
-        if !changed_files.is_empty() {
-            Ok(Some(changed_files))
+        let from_id = if symmetric {
+            repo.merge_base(from.id(), to.id())
+                .wrap_err("Could not find a merge base for symmetric range")?
         } else {
-            Ok(None)
-        }
-    }
-
-    /// Takes in a partial commit SHA-1, and attempts to expand to the full 40-char commit id
-    pub fn expand_partial_commit_id<S: AsRef<str>>(&self, partial_commit_id: S) -> Result<String> {
-        let repo: GitRepo = self.to_repo();
+            from.id()
+        };
 
-        // Don't need to do anything if the commit is already complete
-        // I guess the only issue is not validating it exists. Is that ok?
-        if partial_commit_id.as_ref().len() == 40 {
-            return Ok(partial_commit_id.as_ref().to_string());
-        }
+        Self::files_changed_between_from_repository(
+            &repo,
+            &from_id.to_string(),
+            &to.id().to_string(),
+            &GitDiffOptions::default(),
+        )
+    }
 
-        // We can't reliably succeed if repo is a shallow clone
-        if repo.to_repository()?.is_shallow() {
+    /// Split a range on `...` or `..` and resolve both endpoints, using an already opened repo
+    fn range_from_repository<'repo>(
+        r: &'repo Repository,
+        range: &str,
+    ) -> Result<(Commit<'repo>, Commit<'repo>, bool)> {
+        let (from, to, symmetric) = if let Some((from, to)) = range.split_once("...") {
+            (from, to, true)
+        } else if let Some((from, to)) = range.split_once("..") {
+            (from, to, false)
+        } else {
             return Err(eyre!(
-                "No support for partial commit id expand on shallow clones"
+                "Not a commit range, expected a..b or a...b: {}",
+                range
             ));
-        }
+        };
 
-        let repo = repo.to_repository()?;
+        let resolve = |rev: &str| -> Result<Commit<'repo>> {
+            let rev = if rev.is_empty() { "HEAD" } else { rev };
 
-        let extended_commit = hex::encode(
-            repo.revparse_single(partial_commit_id.as_ref())?
-                .peel_to_commit()?
-                .id()
-                .as_bytes(),
-        );
+            r.revparse_single(rev)
+                .and_then(|object| object.peel_to_commit())
+                .wrap_err(format!("Could not resolve {} in range {}", rev, range))
+        };
 
-        Ok(extended_commit)
+        Ok((resolve(from)?, resolve(to)?, symmetric))
     }
 
-    /// Checks the list of files changed between last 2 commits (`HEAD` and `HEAD~1`).
-    /// Returns `bool` depending on whether any changes were made in `path`.
-    /// A `path` should be relative to the repo root. Can be a file or a directory.
-    pub fn has_path_changed<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
-        let repo = self.to_repo();
-        let git2_repo = repo.to_repository().wrap_err("Could not open repo")?;
+    /// Returns the `n`th parent of `commit`, like `commit^n`. Parents are 1-indexed to match git,
+    /// so `1` is the first parent, and `0` is `commit` itself.
+    /// Returns `None` if `commit` has fewer than `n` parents
+    pub fn nth_parent<S: AsRef<str>>(&self, commit: S, n: usize) -> Result<Option<GitCommitMeta>> {
+        let repo = self.to_repo().to_repository()?;
 
-        // Get `HEAD~1` commit
-        // This could actually be multiple parent commits, if merge commit
-        let head = git2_repo
-            .head()
-            .wrap_err("Could not get HEAD ref")?
-            .peel_to_commit()
-            .wrap_err("Could not convert to commit")?;
-        let head_commit_id = hex::encode(head.id().as_bytes());
-        for commit in head.parents() {
-            let parent_commit_id = hex::encode(commit.id().as_bytes());
+        let commit = Self::commit_from_repository(&repo, commit.as_ref())?;
 
-            if self.has_path_changed_between(&path, &head_commit_id, &parent_commit_id)? {
-                return Ok(true);
-            }
+        if n == 0 {
+            return Ok(Some(GitCommitMeta::from(&commit)));
         }
 
-        Ok(false)
+        Ok(commit
+            .parent(n - 1)
+            .ok()
+            .map(|parent| GitCommitMeta::from(&parent)))
     }
 
-    /// Checks the list of files changed between 2 commits (`commit1` and `commit2`).
-    /// Returns `bool` depending on whether any changes were made in `path`.
-    /// A `path` should be relative to the repo root. Can be a file or a directory.
-    pub fn has_path_changed_between<P: AsRef<Path>, S: AsRef<str>>(
+    /// Returns the commits on `branch` that aren't on `base`, newest first, like `git log base..branch`.
+    /// Both can be anything `git rev-parse` accepts, like a branch, tag or commit id.
+    /// Unlike a symmetric range, commits only on `base` are not included
+    pub fn commits_unique_to<S: AsRef<str>>(
         &self,
-        path: P,
-        commit1: S,
-        commit2: S,
-    ) -> Result<bool> {
-        let commit1 = self
-            .expand_partial_commit_id(commit1.as_ref())
-            .wrap_err("Could not expand partial commit id for commit1")?;
-        let commit2 = self
-            .expand_partial_commit_id(commit2.as_ref())
-            .wrap_err("Could not expand partial commit id for commit2")?;
+        branch: S,
+        base: S,
+    ) -> Result<Vec<GitCommitMeta>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let revwalk = Self::revwalk_range(
+            &repo,
+            Some((base.as_ref(), branch.as_ref())),
+            git2::Sort::TOPOLOGICAL | git2::Sort::TIME,
+        )?;
+
+        revwalk
+            .map(|oid| Ok(GitCommitMeta::from(&repo.find_commit(oid?)?)))
+            .collect()
+    }
 
-        let changed_files = self
-            .list_files_changed_between(&commit1, &commit2)
-            .wrap_err("Error retrieving commit changes")?;
-
-        if let Some(files) = changed_files {
-            for f in files.iter() {
-                if f.to_str()
-                    .wrap_err("Couldn't convert pathbuf to str")?
-                    .starts_with(
-                        &path
-                            .as_ref()
-                            .to_path_buf()
-                            .to_str()
-                            .wrap_err("Couldn't convert pathbuf to str")?,
-                    )
-                {
-                    return Ok(true);
-                }
+    /// Returns the commits reachable from `HEAD` that have no parents, oldest first.
+    /// There is usually one, but repos that merged unrelated histories have more.
+    /// Returns `Err` on shallow clones, since their history may not reach the true root
+    pub fn root_commits(&self) -> Result<Vec<GitCommitMeta>> {
+        let repo = self.to_repo().to_repository()?;
+
+        if repo.is_shallow() {
+            return Err(eyre!("Can't find root commits on shallow clones"));
+        }
+
+        let mut revwalk = repo.revwalk()?;
+        revwalk.set_sorting(git2::Sort::TIME | git2::Sort::REVERSE)?;
+        revwalk.push_head()?;
+
+        let mut roots = Vec::new();
+
+        for oid in revwalk {
+            let commit = repo.find_commit(oid?)?;
+
+            if commit.parent_count() == 0 {
+                roots.push(GitCommitMeta::from(&commit));
             }
         }
 
-        Ok(false)
+        Ok(roots)
     }
 
-    /// Check if new commits exist by performing a shallow clone and comparing branch heads
+    /// Returns `RangeStats` for the commits reachable from `to` but not from `from`, walking the range once.
+    /// Each commit is diffed against its parent, and merge commits are counted without their changes,
+    /// the same as `git log --shortstat from..to`. Both can be anything `git rev-parse` accepts
+    pub fn range_stats<S: AsRef<str>>(&self, from: S, to: S) -> Result<RangeStats> {
+        let repo = self.to_repo().to_repository()?;
+
+        let revwalk =
+            Self::revwalk_range(&repo, Some((from.as_ref(), to.as_ref())), git2::Sort::NONE)?;
+
+        let mut stats = RangeStats::default();
+
+        for oid in revwalk {
+            let commit = repo.find_commit(oid?)?;
+            stats.commits += 1;
+
+            let parent_tree = match commit.parent_count() {
+                0 => None,
+                1 => Some(commit.parent(0)?.tree()?),
+                _ => continue,
+            };
+
+            let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+
+            let diff_stats = diff.stats()?;
+            stats.insertions += diff_stats.insertions();
+            stats.deletions += diff_stats.deletions();
+
+            stats.files_touched.extend(
+                diff.deltas()
+                    .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+                    .map(Path::to_path_buf),
+            );
+        }
+
+        Ok(stats)
+    }
+
+    /// Returns the commits that touched `path`, newest first, similar to `git log -- path`.
+    /// Walks `from..to` if `range` is given, otherwise all of the history reachable from HEAD. A commit
+    /// is included if its diff against its first parent changes anything under `path`. `max` limits
+    /// the number of commits returned
+    pub fn commits_touching_path<P: AsRef<Path>, S: AsRef<str>>(
+        &self,
+        path: P,
+        range: Option<(S, S)>,
+        max: Option<usize>,
+    ) -> Result<Vec<GitCommitMeta>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let revwalk = Self::revwalk_range(
+            &repo,
+            range
+                .as_ref()
+                .map(|(from, to)| (from.as_ref(), to.as_ref())),
+            git2::Sort::TOPOLOGICAL | git2::Sort::TIME,
+        )?;
+
+        let mut diff_options = git2::DiffOptions::new();
+        diff_options.pathspec(path.as_ref());
+
+        let mut commits = Vec::new();
+
+        for oid in revwalk {
+            if Some(commits.len()) == max {
+                break;
+            }
+
+            let commit = repo.find_commit(oid?)?;
+
+            let parent_tree = if commit.parent_count() > 0 {
+                Some(commit.parent(0)?.tree()?)
+            } else {
+                None
+            };
+
+            let diff = repo.diff_tree_to_tree(
+                parent_tree.as_ref(),
+                Some(&commit.tree()?),
+                Some(&mut diff_options),
+            )?;
+
+            if diff.deltas().len() > 0 {
+                commits.push(GitCommitMeta::from(&commit));
+            }
+        }
+
+        Ok(commits)
+    }
+
+    /// Returns the commits in `from..to` if `range` is given, otherwise all of the history reachable from HEAD,
+    /// newest first, like `git log`. `max` limits the number of commits returned.
+    /// Set `with_signatures` to fill in `GitCommitMeta.signed` for each commit. It is off by default,
+    /// since it reads each commit object again
+    pub fn commit_log<S: AsRef<str>>(
+        &self,
+        range: Option<(S, S)>,
+        max: Option<usize>,
+        with_signatures: bool,
+    ) -> Result<Vec<GitCommitMeta>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let revwalk = Self::revwalk_range(
+            &repo,
+            range
+                .as_ref()
+                .map(|(from, to)| (from.as_ref(), to.as_ref())),
+            git2::Sort::TOPOLOGICAL | git2::Sort::TIME,
+        )?;
+
+        let mut commits = Vec::new();
+
+        for oid in revwalk {
+            if Some(commits.len()) == max {
+                break;
+            }
+
+            let oid = oid?;
+            let mut commit = GitCommitMeta::from(&repo.find_commit(oid)?);
+
+            if with_signatures {
+                commit.signed = match repo.extract_signature(&oid, None) {
+                    Ok(_) => Some(true),
+                    Err(e) if e.code() == ErrorCode::NotFound => Some(false),
+                    Err(e) => {
+                        return Err(e).wrap_err(format!("Could not read signature of {}", oid))
+                    }
+                };
+            }
+
+            commits.push(commit);
+        }
+
+        Ok(commits)
+    }
+
+    /// Counts the commits in `from..to`, or reachable from `HEAD` if `None`, by the domain of each author's email,
+    /// the part after `@`, lowercased. Commits without a valid email are counted under `"unknown"`
+    pub fn commits_by_domain<S: AsRef<str>>(
+        &self,
+        range: Option<(S, S)>,
+    ) -> Result<HashMap<String, usize>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let revwalk = Self::revwalk_range(
+            &repo,
+            range
+                .as_ref()
+                .map(|(from, to)| (from.as_ref(), to.as_ref())),
+            git2::Sort::NONE,
+        )?;
+
+        let mut domains = HashMap::new();
+
+        for oid in revwalk {
+            let commit = repo.find_commit(oid?)?;
+            let author = commit.author();
+
+            let domain = match author.email().and_then(|email| email.rsplit_once('@')) {
+                Some((user, domain)) if !user.is_empty() && !domain.is_empty() => {
+                    domain.to_lowercase()
+                }
+                _ => "unknown".to_string(),
+            };
+
+            *domains.entry(domain).or_insert(0) += 1;
+        }
+
+        Ok(domains)
+    }
+
+    /// Walks history from `start`, or `HEAD` if `None`, newest first, and returns the first commit that `pred`
+    /// returns `true` for. The walk stops at the first match, so the rest of the history isn't read.
+    /// Returns `None` if no commit matches. `start` can be anything `git rev-parse` accepts
+    pub fn find_commit_where<S: AsRef<str>, F: FnMut(&GitCommitMeta) -> bool>(
+        &self,
+        start: Option<S>,
+        mut pred: F,
+    ) -> Result<Option<GitCommitMeta>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let mut revwalk = repo.revwalk()?;
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+
+        if let Some(start) = start {
+            revwalk.push(Self::resolve_commit_id(&repo, start.as_ref())?)?;
+        } else {
+            revwalk.push_head()?;
+        }
+
+        for oid in revwalk {
+            let commit = GitCommitMeta::from(&repo.find_commit(oid?)?);
+
+            if pred(&commit) {
+                return Ok(Some(commit));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Count the commits reachable from `to` but not from `from`, like `git rev-list --count from..to`.
+    /// Either can be anything `git rev-parse` accepts, like a commit id, branch or tag.
+    ///
+    /// In a shallow clone, history may be cut off before reaching `from`. If the walk reaches a
+    /// shallow boundary, or `from` isn't in the clone at all, the count is returned with `complete: false`
+    ///
+    /// *Note:* Counting in shallow clones requires `git` CLI to be installed
+    pub fn count_commits_between(&self, from: &str, to: &str) -> Result<CommitCount> {
+        let repo = self.to_repo().to_repository()?;
+
+        let to_id = Self::resolve_commit_id(&repo, to)?;
+
+        if !repo.is_shallow() {
+            let revwalk = Self::revwalk_range(&repo, Some((from, to)), git2::Sort::NONE)?;
+
+            return Ok(CommitCount {
+                count: revwalk.count(),
+                complete: true,
+            });
+        }
+
+        // git2 can't walk past the missing parents of a shallow boundary, so use the git CLI
+        let path = self.path.as_ref().wrap_err("No path set to open")?;
+        let to_id = to_id.to_string();
+
+        let from_commit = repo
+            .revparse_single(from)
+            .and_then(|object| object.peel_to_commit());
+
+        let (range, mut complete) = match from_commit {
+            Ok(from_commit) => (format!("{}..{}", from_commit.id(), to_id), true),
+            // `from` is older than the shallow boundary
+            Err(e) if e.code() == ErrorCode::NotFound => (to_id, false),
+            Err(e) => return Err(e).wrap_err(format!("Could not resolve {}", from)),
+        };
+
+        let output = cli::run_git(Some(path), &["rev-list", &range])?;
+        let shallow_commits = shallow_commits(&repo)?;
+
+        let mut count = 0;
+
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            count += 1;
+
+            if shallow_commits.contains(&Oid::from_str(line)?) {
+                complete = false;
+            }
+        }
+
+        Ok(CommitCount { count, complete })
+    }
+
+    /// Returns `true` if `commit1` and `commit2` have identical trees, ignoring commit metadata like
+    /// the message, author or parents. Cheaper than a diff, and handy for spotting unchanged rebases
+    pub fn same_tree<S: AsRef<str>>(&self, commit1: S, commit2: S) -> Result<bool> {
+        let repo = self.to_repo().to_repository()?;
+
+        let tree1 = Self::commit_from_repository(&repo, commit1.as_ref())?.tree_id();
+        let tree2 = Self::commit_from_repository(&repo, commit2.as_ref())?.tree_id();
+
+        Ok(tree1 == tree2)
+    }
+
+    /// Returns `true` if `commit` didn't change any content, because its tree is the same as its first parent's.
+    /// Useful for skipping bookkeeping commits like no-op merges. A root commit is only empty if its tree is empty
+    pub fn is_empty_commit<S: AsRef<str>>(&self, commit: S) -> Result<bool> {
+        let repo = self.to_repo().to_repository()?;
+
+        let commit = Self::commit_from_repository(&repo, commit.as_ref())?;
+
+        if commit.parent_count() > 0 {
+            Ok(commit.tree_id() == commit.parent(0)?.tree_id())
+        } else {
+            Ok(commit.tree()?.is_empty())
+        }
+    }
+
+    /// Returns the raw header of `commit`, exactly as git hashed it. Unlike the parsed metadata,
+    /// this keeps the `tree`, `parent`, `author`, `committer` and `gpgsig` lines as-is, for verifying signatures
+    pub fn commit_raw_header<S: AsRef<str>>(&self, commit: S) -> Result<String> {
+        let repo = self.to_repo().to_repository()?;
+
+        let commit = Self::commit_from_repository(&repo, commit.as_ref())?;
+
+        String::from_utf8(commit.raw_header_bytes().to_vec()).wrap_err(format!(
+            "Header of commit {} is not valid utf-8",
+            commit.id()
+        ))
+    }
+
+    /// Returns the `DiffStats` of the changes from `commit1` to `commit2`.
+    /// With default `options`, the counts match `git diff --shortstat commit1 commit2`.
+    /// Hunk counts match `git diff -U<context_lines> --inter-hunk-context=<interhunk_lines>`
+    pub fn diff_stats_between<S: AsRef<str>>(
+        &self,
+        commit1: S,
+        commit2: S,
+        options: &GitDiffOptions,
+    ) -> Result<DiffStats> {
+        let repo = self.to_repo().to_repository()?;
+
+        let tree1 = Self::commit_from_repository(&repo, commit1.as_ref())?.tree()?;
+        let tree2 = Self::commit_from_repository(&repo, commit2.as_ref())?.tree()?;
+
+        let diff =
+            repo.diff_tree_to_tree(Some(&tree1), Some(&tree2), Some(&mut options.to_git2()))?;
+
+        let exclude = options.exclude_pathspec()?;
+
+        let mut stats = DiffStats::default();
+
+        for idx in 0..diff.deltas().len() {
+            let patch = if let Some(patch) = git2::Patch::from_diff(&diff, idx)? {
+                patch
+            } else {
+                continue;
+            };
+
+            let delta = patch.delta();
+            let path = if let Some(p) = delta.new_file().path().or_else(|| delta.old_file().path())
+            {
+                p
+            } else {
+                continue;
+            };
+
+            if let Some(exclude) = &exclude {
+                if exclude.matches_path(path, git2::PathspecFlags::DEFAULT) {
+                    continue;
+                }
+            }
+
+            let (_context, insertions, deletions) = patch.line_stats()?;
+
+            stats.files_changed += 1;
+            stats.insertions += insertions;
+            stats.deletions += deletions;
+            stats.hunks += patch.num_hunks();
+        }
+
+        Ok(stats)
+    }
+
+    /// Returns the unified diff of the changes from `commit1` to `commit2`, like `git diff commit1 commit2`.
+    /// Binary files are listed, but their contents are not included
+    pub fn patch_between<S: AsRef<str>>(
+        &self,
+        commit1: S,
+        commit2: S,
+        options: &GitDiffOptions,
+    ) -> Result<String> {
+        let repo = self.to_repo().to_repository()?;
+
+        let tree1 = Self::commit_from_repository(&repo, commit1.as_ref())?.tree()?;
+        let tree2 = Self::commit_from_repository(&repo, commit2.as_ref())?.tree()?;
+
+        let diff =
+            repo.diff_tree_to_tree(Some(&tree1), Some(&tree2), Some(&mut options.to_git2()))?;
+
+        let exclude = options.exclude_pathspec()?;
+
+        let mut patch = Vec::new();
+
+        diff.print(git2::DiffFormat::Patch, |delta, _hunk, line| {
+            if let Some(exclude) = &exclude {
+                if let Some(path) = delta.new_file().path().or_else(|| delta.old_file().path()) {
+                    if exclude.matches_path(path, git2::PathspecFlags::DEFAULT) {
+                        return true;
+                    }
+                }
+            }
+
+            // Only content lines are missing their prefix. Headers already include theirs
+            if let origin @ ('+' | '-' | ' ') = line.origin() {
+                patch.push(origin as u8);
+            }
+            patch.extend_from_slice(line.content());
+
+            true
+        })?;
+
+        Ok(String::from_utf8_lossy(&patch).to_string())
+    }
+
+    /// Returns the files changed between `commit` (or `HEAD` if `None`) and the working tree,
+    /// like `git diff --name-only <commit>`. Both staged and unstaged edits are included,
+    /// but untracked files are not
+    pub fn diff_commit_to_workdir<S: AsRef<str>>(&self, commit: Option<S>) -> Result<Vec<PathBuf>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let tree = match commit {
+            Some(c) => Self::commit_from_repository(&repo, c.as_ref())?.tree()?,
+            None => repo.head()?.peel_to_tree()?,
+        };
+
+        let diff = repo.diff_tree_to_workdir_with_index(Some(&tree), None)?;
+
+        Ok(diff
+            .deltas()
+            .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+            .map(Path::to_path_buf)
+            .collect())
+    }
+
+    /// Returns the paths of every file in the index (staging area), like `git ls-files --cached`.
+    /// Files with merge conflicts are only listed once
+    pub fn index_files(&self) -> Result<Vec<PathBuf>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let mut files: Vec<PathBuf> = repo
+            .index()?
+            .iter()
+            .map(|entry| PathBuf::from(String::from_utf8_lossy(&entry.path).to_string()))
+            .collect();
+        // A conflicted file has an entry for each side of the merge
+        files.dedup();
+
+        Ok(files)
+    }
+
+    /// Returns the files staged in the index, compared to `HEAD`, like `git diff --cached --name-status`.
+    /// Unstaged edits in the working tree aren't included. Before the first commit, every staged file is `Delta::Added`
+    pub fn staged_changes(&self) -> Result<Vec<(PathBuf, Delta)>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let head_tree = match repo.head() {
+            Ok(head) => Some(head.peel_to_tree()?),
+            Err(e) if e.code() == ErrorCode::UnbornBranch => None,
+            Err(e) => return Err(e).wrap_err("Could not get HEAD ref"),
+        };
+
+        let diff = repo.diff_tree_to_index(head_tree.as_ref(), None, None)?;
+
+        Ok(Self::changed_paths(&diff))
+    }
+
+    /// Returns a `Result<Option<Vec<PathBuf>>>` containing files changed between `commit` and `commit~1` (the previous commit).
+    /// For merge commits, files changed against each parent are included.
+    /// Returns `Ok(Some(vec![]))` if `commit` made no changes, and `Ok(None)` if `commit` has no parents to compare against
+    pub fn list_files_changed_at<S: AsRef<str>>(&self, commit: S) -> Result<Option<Vec<PathBuf>>> {
+        let git2_repo = self.to_repo().to_repository()?;
+
+        let commit = Self::expand_partial_commit_id_from_repository(&git2_repo, commit.as_ref())?;
+
+        let oid = Oid::from_str(&commit)?;
+        let git2_commit = git2_repo.find_commit(oid)?;
+
+        // A root commit has nothing to compare against
+        if git2_commit.parent_count() == 0 {
+            return Ok(None);
+        }
+
+        let mut changed_files = Vec::new();
+
+        for parent in git2_commit.parents() {
+            let parent_commit_id = hex::encode(parent.id().as_bytes());
+
+            if let Some(path_vec) = Self::files_changed_between_from_repository(
+                &git2_repo,
+                &parent_commit_id,
+                &commit,
+                &GitDiffOptions::default(),
+            )? {
+                for p in path_vec {
+                    changed_files.push(p);
+                }
+            }
+        }
+
+        Ok(Some(changed_files))
+    }
+
+    /// Returns `commit` along with the files it changed, opening the repo once.
+    /// Like `list_files_changed_at()`, but a root commit lists all of its files as added,
+    /// and a merge commit is only compared against its first parent
+    pub fn commit_change_summary<S: AsRef<str>>(&self, commit: S) -> Result<CommitChangeSummary> {
+        let repo = self.to_repo().to_repository()?;
+
+        let commit = Self::commit_from_repository(&repo, commit.as_ref())?;
+
+        let parent_tree = if commit.parent_count() > 0 {
+            Some(commit.parent(0)?.tree()?)
+        } else {
+            None
+        };
+
+        let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+
+        Ok(CommitChangeSummary {
+            commit: GitCommitMeta::from(&commit),
+            files: Self::changed_paths(&diff),
+        })
+    }
+
+    /// Returns the files changed between the trees with ids `tree1` and `tree2`, and how each changed.
+    /// Lower level than the commit-based diffs, for when tree ids are already known, like from notes.
+    /// Returns `Err` if either id isn't a full tree id. Commits are not resolved to their tree
+    pub fn diff_trees(&self, tree1: &str, tree2: &str) -> Result<Vec<(PathBuf, Delta)>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let find_tree = |id: &str| {
+            let oid = Oid::from_str(id).wrap_err(format!("Not a valid tree id: {}", id))?;
+
+            let object = repo
+                .find_object(oid, None)
+                .wrap_err(format!("Tree not found: {}", id))?;
+
+            object.into_tree().map_err(|object| {
+                eyre!(
+                    "{} is not a tree, it is a {}",
+                    id,
+                    object.kind().map_or("unknown object", |kind| kind.str())
+                )
+            })
+        };
+
+        let tree1 = find_tree(tree1)?;
+        let tree2 = find_tree(tree2)?;
+
+        let diff = repo.diff_tree_to_tree(Some(&tree1), Some(&tree2), None)?;
+
+        Ok(Self::changed_paths(&diff))
+    }
+
+    /// Returns the path of each delta in `diff`, and how it changed. Deleted files use their old path
+    fn changed_paths(diff: &git2::Diff) -> Vec<(PathBuf, Delta)> {
+        diff.deltas()
+            .filter_map(|delta| {
+                let path = delta
+                    .new_file()
+                    .path()
+                    .or_else(|| delta.old_file().path())?;
+                Some((path.to_path_buf(), delta.status().into()))
+            })
+            .collect()
+    }
+
+    /// Takes in a partial commit SHA-1, and attempts to expand to the full 40-char commit id
+    #[instrument(
+        skip_all,
+        fields(
+            url = %self.url.trim_auth(),
+            partial_commit_id = partial_commit_id.as_ref()
+        )
+    )]
+    pub fn expand_partial_commit_id<S: AsRef<str>>(&self, partial_commit_id: S) -> Result<String> {
+        let repo = self.to_repo().to_repository()?;
+
+        Self::expand_partial_commit_id_from_repository(&repo, partial_commit_id.as_ref())
+    }
+
+    /// Check that the `HEAD` commit of the repo at `self.path` is `expected`, such as after cloning a branch,
+    /// in case the branch moved between listing its HEAD and cloning. Partial ids are expanded first, which
+    /// requires a deep clone. Returns `Err` with both ids if they don't match
+    pub fn verify_head(&self, expected: &str) -> Result<()> {
+        let repo = self.to_repo().to_repository()?;
+
+        let head_id = repo
+            .head()
+            .wrap_err("Could not get HEAD ref")?
+            .peel_to_commit()
+            .wrap_err("HEAD does not point at a commit")?
+            .id()
+            .to_string();
+
+        let expected_id = if expected.len() == 40 {
+            expected.to_ascii_lowercase()
+        } else {
+            Self::expand_partial_commit_id_from_repository(&repo, expected)
+                .wrap_err(format!("Could not expand expected commit id {}", expected))?
+        };
+
+        if head_id != expected_id {
+            return Err(eyre!(
+                "HEAD is at {}, but expected {}",
+                head_id,
+                expected_id
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the `GitCommitMeta` for each of `ids`, in the same order.
+    /// The repo is only opened once, so this is preferred over looking up commits one at a time.
+    /// Returns `Err` naming the first id that could not be found.
+    /// Use `commits_meta_optional()` to get `None` for missing commits instead
+    pub fn commits_meta(&self, ids: &[String]) -> Result<Vec<GitCommitMeta>> {
+        self.commits_meta_optional(ids)?
+            .into_iter()
+            .zip(ids)
+            .map(|(meta, id)| meta.wrap_err(format!("Commit not found: {}", id)))
+            .collect()
+    }
+
+    /// Returns the `GitCommitMeta` for each of `ids`, in the same order.
+    /// Commits that can't be found in the repo, or partial ids matching more than one commit, are returned as `None`.
+    /// Other errors, like a malformed id or a corrupt object, are returned as `Err`
+    pub fn commits_meta_optional(&self, ids: &[String]) -> Result<Vec<Option<GitCommitMeta>>> {
+        let repo = self.to_repo().to_repository()?;
+
+        ids.iter()
+            .map(|id| match Self::commit_from_repository(&repo, id) {
+                Ok(commit) => Ok(Some(GitCommitMeta::from(&commit))),
+                Err(e) => match e.downcast_ref::<git2::Error>().map(|e| e.code()) {
+                    Some(ErrorCode::NotFound | ErrorCode::Ambiguous) => Ok(None),
+                    _ => Err(e.wrap_err(format!("Could not read commit {}", id))),
+                },
+            })
+            .collect()
+    }
+
+    /// Checks the list of files changed between last 2 commits (`HEAD` and `HEAD~1`).
+    /// Returns `bool` depending on whether any changes were made in `path`.
+    /// A `path` should be relative to the repo root. Can be a file or a directory.
+    pub fn has_path_changed<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        let git2_repo = self
+            .to_repo()
+            .to_repository()
+            .wrap_err("Could not open repo")?;
+
+        // Get `HEAD~1` commit
+        // This could actually be multiple parent commits, if merge commit
+        let head = git2_repo
+            .head()
+            .wrap_err("Could not get HEAD ref")?
+            .peel_to_commit()
+            .wrap_err("Could not convert to commit")?;
+        let head_commit_id = hex::encode(head.id().as_bytes());
+        for commit in head.parents() {
+            let parent_commit_id = hex::encode(commit.id().as_bytes());
+
+            if Self::path_changed_between_from_repository(
+                &git2_repo,
+                path.as_ref(),
+                &head_commit_id,
+                &parent_commit_id,
+            )? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Checks the list of files changed between 2 commits (`commit1` and `commit2`).
+    /// Returns `bool` depending on whether any changes were made in `path`.
+    /// A `path` should be relative to the repo root. Can be a file or a directory.
+    pub fn has_path_changed_between<P: AsRef<Path>, S: AsRef<str>>(
+        &self,
+        path: P,
+        commit1: S,
+        commit2: S,
+    ) -> Result<bool> {
+        let repo = self.to_repo().to_repository()?;
+
+        Self::path_changed_between_from_repository(
+            &repo,
+            path.as_ref(),
+            commit1.as_ref(),
+            commit2.as_ref(),
+        )
+    }
+
+    /// Checks each of `paths` for changes between `commit1` and `commit2`, the same as `has_path_changed_between()`.
+    /// The diff is only computed once, so this is faster than checking many paths one at a time.
+    /// Every path in `paths` is a key of the returned map
+    pub fn paths_changed<S: AsRef<str>>(
+        &self,
+        paths: &[PathBuf],
+        commit1: S,
+        commit2: S,
+    ) -> Result<HashMap<PathBuf, bool>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let commit1 = Self::expand_partial_commit_id_from_repository(&repo, commit1.as_ref())
+            .wrap_err("Could not expand partial commit id for commit1")?;
+        let commit2 = Self::expand_partial_commit_id_from_repository(&repo, commit2.as_ref())
+            .wrap_err("Could not expand partial commit id for commit2")?;
+
+        let changed_files = Self::files_changed_between_from_repository(
+            &repo,
+            &commit1,
+            &commit2,
+            &GitDiffOptions::default(),
+        )
+        .wrap_err("Error retrieving commit changes")?
+        .unwrap_or_default();
+
+        paths
+            .iter()
+            .map(|path| {
+                Ok((
+                    path.clone(),
+                    Self::path_in_changed_files(&changed_files, path)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Returns the commits reachable from `HEAD` that changed `path`, newest first, like `git log -- path`.
+    /// Merge commits are only included if `path` differs from every parent.
+    /// With `follow_renames`, history continues through renames of `path`, like `git log --follow`.
+    /// Renames are detected with `git2::Diff::find_similar()`, so only single files can be followed
+    pub fn path_history<P: AsRef<Path>>(
+        &self,
+        path: P,
+        follow_renames: bool,
+    ) -> Result<Vec<GitCommitMeta>> {
+        let repo = self.to_repo().to_repository()?;
+
+        Self::path_history_from_repository(&repo, path.as_ref(), follow_renames, None)
+    }
+
+    /// Returns the newest commit reachable from `HEAD` that changed `path`, or `None` if no commits did.
+    /// See `path_history()` for how `follow_renames` is handled
+    pub fn last_commit_for_path<P: AsRef<Path>>(
+        &self,
+        path: P,
+        follow_renames: bool,
+    ) -> Result<Option<GitCommitMeta>> {
+        let repo = self.to_repo().to_repository()?;
+
+        Ok(
+            Self::path_history_from_repository(&repo, path.as_ref(), follow_renames, Some(1))?
+                .pop(),
+        )
+    }
+
+    /// Walk history from `HEAD` for commits that changed `path`, stopping after `limit` commits
+    fn path_history_from_repository(
+        r: &Repository,
+        path: &Path,
+        follow_renames: bool,
+        limit: Option<usize>,
+    ) -> Result<Vec<GitCommitMeta>> {
+        let mut revwalk = r.revwalk()?;
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+        revwalk.push_head()?;
+
+        let mut path = path.to_path_buf();
+        let mut history = Vec::new();
+
+        for oid in revwalk {
+            if limit.is_some_and(|limit| history.len() >= limit) {
+                break;
+            }
+
+            let commit = r.find_commit(oid?)?;
+            let tree = commit.tree()?;
+
+            let entry_id = |tree: &git2::Tree| tree.get_path(&path).ok().map(|e| e.id());
+            let current = entry_id(&tree);
+
+            let parents = commit.parents().collect::<Vec<Commit>>();
+
+            // Unchanged compared to any parent, so another commit made the change
+            let mut unchanged = false;
+            for parent in &parents {
+                if entry_id(&parent.tree()?) == current {
+                    unchanged = true;
+                    break;
+                }
+            }
+
+            // A root commit changes `path` only if it adds it
+            if unchanged || (parents.is_empty() && current.is_none()) {
+                continue;
+            }
+
+            history.push(GitCommitMeta::from(&commit));
+
+            // If `path` was added here, look for the path it was renamed from
+            if follow_renames && current.is_some() {
+                if let Some(parent) = parents.first() {
+                    let parent_tree = parent.tree()?;
+
+                    if entry_id(&parent_tree).is_none() {
+                        if let Some(old_path) =
+                            Self::renamed_from_repository(r, &parent_tree, &tree, &path)?
+                        {
+                            path = old_path;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(history)
+    }
+
+    /// Returns the old path of `path`, if it was renamed between `old_tree` and `new_tree`
+    fn renamed_from_repository(
+        r: &Repository,
+        old_tree: &git2::Tree,
+        new_tree: &git2::Tree,
+        path: &Path,
+    ) -> Result<Option<PathBuf>> {
+        let mut diff = r.diff_tree_to_tree(Some(old_tree), Some(new_tree), None)?;
+        diff.find_similar(Some(git2::DiffFindOptions::new().renames(true)))?;
+
+        Ok(diff
+            .deltas()
+            .find(|delta| {
+                delta.status() == git2::Delta::Renamed && delta.new_file().path() == Some(path)
+            })
+            .and_then(|delta| delta.old_file().path().map(Path::to_path_buf)))
+    }
+
+    /// Expand a partial commit id to the full 40-char commit id, using an already opened repo
+    fn expand_partial_commit_id_from_repository(
+        r: &Repository,
+        partial_commit_id: &str,
+    ) -> Result<String> {
+        // Don't need to do anything if the commit is already complete
+        // I guess the only issue is not validating it exists. Is that ok?
+        if partial_commit_id.len() == 40 {
+            return Ok(partial_commit_id.to_string());
+        }
+
+        // We can't reliably succeed if repo is a shallow clone
+        if r.is_shallow() {
+            return Err(eyre!(
+                "No support for partial commit id expand on shallow clones"
+            ));
+        }
+
+        let extended_commit = hex::encode(
+            r.revparse_single(partial_commit_id)?
+                .peel_to_commit()?
+                .id()
+                .as_bytes(),
+        );
+
+        Ok(extended_commit)
+    }
+
+    /// Return the `git2::Commit` for a full or partial commit id, using an already opened repo
+    pub(crate) fn commit_from_repository<'repo>(
+        r: &'repo Repository,
+        commit_id: &str,
+    ) -> Result<Commit<'repo>> {
+        let commit_id = Self::expand_partial_commit_id_from_repository(r, commit_id)?;
+
+        Ok(r.find_commit(Oid::from_str(&commit_id)?)?)
+    }
+
+    /// Resolve `rev` to the id of the commit it points at. `rev` can be anything `git rev-parse` accepts
+    fn resolve_commit_id(r: &Repository, rev: &str) -> Result<Oid> {
+        r.revparse_single(rev)
+            .and_then(|object| object.peel_to_commit())
+            .map(|commit| commit.id())
+            .wrap_err(format!("Could not resolve {}", rev))
+    }
+
+    /// Return a `git2::Revwalk` sorted by `sort`, over `from..to` if `range` is given,
+    /// otherwise over all of the history reachable from `HEAD`
+    fn revwalk_range<'repo>(
+        r: &'repo Repository,
+        range: Option<(&str, &str)>,
+        sort: git2::Sort,
+    ) -> Result<git2::Revwalk<'repo>> {
+        let mut revwalk = r.revwalk()?;
+        // Sorting resets the walk, so it has to be set first
+        revwalk.set_sorting(sort)?;
+
+        if let Some((from, to)) = range {
+            revwalk.push(Self::resolve_commit_id(r, to)?)?;
+            revwalk.hide(Self::resolve_commit_id(r, from)?)?;
+        } else {
+            revwalk.push_head()?;
+        }
+
+        Ok(revwalk)
+    }
+
+    /// Return the tree at `path` in `commit`, and `path` to prefix its entries with.
+    /// If `path` is `None` or empty, the root tree of `commit` is returned with an empty prefix
+    fn subtree_at<'repo>(
+        r: &'repo Repository,
+        commit: &str,
+        path: Option<&Path>,
+    ) -> Result<(git2::Tree<'repo>, PathBuf)> {
+        let root = Self::commit_from_repository(r, commit)?.tree()?;
+
+        match path {
+            Some(p) if !p.as_os_str().is_empty() => {
+                let entry = root.get_path(p).wrap_err("Path not found in commit")?;
+
+                let tree = if let Ok(tree) = entry.to_object(r)?.into_tree() {
+                    tree
+                } else {
+                    return Err(eyre!("Path is not a directory: {:?}", p));
+                };
+
+                Ok((tree, p.to_path_buf()))
+            }
+            _ => Ok((root, PathBuf::new())),
+        }
+    }
+
+    /// List the files changed between `commit1` and `commit2`, using an already opened repo
+    fn files_changed_between_from_repository(
+        r: &Repository,
+        commit1: &str,
+        commit2: &str,
+        options: &GitDiffOptions,
+    ) -> Result<Option<Vec<PathBuf>>> {
+        let git2_commit1 = Self::commit_from_repository(r, commit1)?.tree()?;
+        let git2_commit2 = Self::commit_from_repository(r, commit2)?.tree()?;
+
+        let diff = r.diff_tree_to_tree(
+            Some(&git2_commit1),
+            Some(&git2_commit2),
+            Some(&mut options.to_git2()),
+        )?;
+
+        let exclude = options.exclude_pathspec()?;
+
+        let mut paths = Vec::new();
+
+        diff.print(git2::DiffFormat::NameOnly, |delta, _hunk, _line| {
+            let delta_path = if let Some(p) = delta.new_file().path() {
+                p
+            } else {
+                return false;
+            };
+
+            let excluded = exclude.as_ref().is_some_and(|exclude| {
+                exclude.matches_path(delta_path, git2::PathspecFlags::DEFAULT)
+            });
+
+            if !excluded {
+                paths.push(delta_path.to_path_buf());
+            }
+            true
+        })
+        .wrap_err("File path not found in new commit to compare")?;
+
+        // An empty list means the trees are identical
+        Ok(Some(paths))
+    }
+
+    /// Check whether `path` changed between `commit1` and `commit2`, using an already opened repo
+    fn path_changed_between_from_repository(
+        r: &Repository,
+        path: &Path,
+        commit1: &str,
+        commit2: &str,
+    ) -> Result<bool> {
+        let commit1 = Self::expand_partial_commit_id_from_repository(r, commit1)
+            .wrap_err("Could not expand partial commit id for commit1")?;
+        let commit2 = Self::expand_partial_commit_id_from_repository(r, commit2)
+            .wrap_err("Could not expand partial commit id for commit2")?;
+
+        let changed_files = Self::files_changed_between_from_repository(
+            r,
+            &commit1,
+            &commit2,
+            &GitDiffOptions::default(),
+        )
+        .wrap_err("Error retrieving commit changes")?;
+
+        Self::path_in_changed_files(&changed_files.unwrap_or_default(), path)
+    }
+
+    /// Returns `true` if any of `changed_files` are in `path`
+    fn path_in_changed_files(changed_files: &[PathBuf], path: &Path) -> Result<bool> {
+        let path = path.to_str().wrap_err("Couldn't convert pathbuf to str")?;
+
+        for f in changed_files.iter() {
+            if f.to_str()
+                .wrap_err("Couldn't convert pathbuf to str")?
+                .starts_with(path)
+            {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Returns the entries of the tree at `path` in `commit`, similar to `git ls-tree`.
+    /// If `path` is `None`, the entries at the root of the repo are returned.
+    /// Symlinks are reported as `TreeEntryKind::Symlink`, rather than as regular files
+    pub fn list_tree<S: AsRef<str>, P: AsRef<Path>>(
+        &self,
+        commit: S,
+        path: Option<P>,
+    ) -> Result<Vec<TreeEntry>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let (tree, prefix) =
+            Self::subtree_at(&repo, commit.as_ref(), path.as_ref().map(|p| p.as_ref()))?;
+
+        let mut entries = Vec::new();
+
+        for entry in tree.iter() {
+            let name = if let Some(name) = entry.name() {
+                name
+            } else {
+                return Err(eyre!("Tree entry name not valid utf-8"));
+            };
+
+            let kind = if let Some(kind) = TreeEntryKind::from_filemode(entry.filemode()) {
+                kind
+            } else {
+                return Err(eyre!(
+                    "Unknown filemode {:o} for tree entry {:?}",
+                    entry.filemode(),
+                    name
+                ));
+            };
+
+            entries.push(TreeEntry {
+                path: prefix.join(name),
+                kind,
+                id: hex::encode(entry.id().as_bytes()),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    /// Returns the path of every file in the tree of `commit`, like `git ls-tree -r --name-only`.
+    /// If `path` is provided, only files under that directory are listed.
+    /// Paths are relative to the repo root. Submodules are not listed, since they aren't blobs
+    pub fn list_all_files<S: AsRef<str>, P: AsRef<Path>>(
+        &self,
+        commit: S,
+        path: Option<P>,
+    ) -> Result<Vec<PathBuf>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let (tree, prefix) =
+            Self::subtree_at(&repo, commit.as_ref(), path.as_ref().map(|p| p.as_ref()))?;
+
+        let mut files = Vec::new();
+
+        tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() == Some(git2::ObjectType::Blob) {
+                let name = String::from_utf8_lossy(entry.name_bytes());
+                files.push(prefix.join(dir).join(name.as_ref()));
+            }
+
+            git2::TreeWalkResult::Ok
+        })?;
+
+        Ok(files)
+    }
+
+    /// Returns the number of files in the tree of `commit` per file extension.
+    /// Extensions are lowercased, and files without an extension (including dotfiles like `.gitignore`)
+    /// are counted under `""`. Symlinks and submodules are not counted
+    pub fn file_extension_stats<S: AsRef<str>>(&self, commit: S) -> Result<HashMap<String, usize>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let tree = Self::commit_from_repository(&repo, commit.as_ref())?.tree()?;
+
+        let mut stats = HashMap::new();
+
+        tree.walk(git2::TreeWalkMode::PreOrder, |_root, entry| {
+            if let Some(TreeEntryKind::File | TreeEntryKind::Executable) =
+                TreeEntryKind::from_filemode(entry.filemode())
+            {
+                let name = String::from_utf8_lossy(entry.name_bytes());
+
+                let extension = Path::new(name.as_ref())
+                    .extension()
+                    .map(|ext| ext.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+
+                *stats.entry(extension).or_insert(0) += 1;
+            }
+
+            git2::TreeWalkResult::Ok
+        })?;
+
+        Ok(stats)
+    }
+
+    /// Returns the `BlobInfo` of the file at `path` in `commit`, or `None` if `path` doesn't exist.
+    /// Returns `Err` if `path` is not a file, like a directory or submodule
+    pub fn blob_info<S: AsRef<str>, P: AsRef<Path>>(
+        &self,
+        commit: S,
+        path: P,
+    ) -> Result<Option<BlobInfo>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let tree = Self::commit_from_repository(&repo, commit.as_ref())?.tree()?;
+
+        let entry = match tree.get_path(path.as_ref()) {
+            Ok(entry) => entry,
+            Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let blob = if let Ok(blob) = entry.to_object(&repo)?.into_blob() {
+            blob
+        } else {
+            return Err(eyre!("Path is not a file: {:?}", path.as_ref()));
+        };
+
+        Ok(Some(BlobInfo {
+            oid: blob.id().to_string(),
+            size: blob.size() as u64,
+            is_binary: blob.is_binary(),
+        }))
+    }
+
+    /// Returns a reader over the contents of the file at `path` in `commit`, for piping into a hash
+    /// or writer. Like `file_at_commit()` without resolving symlinks, but returns `Err` if `path`
+    /// doesn't exist in `commit`, or isn't a file.
+    ///
+    /// *Note:* `git2` loads the whole blob into memory, so this doesn't stream from disk yet
+    pub fn file_reader_at_commit<S: AsRef<str>, P: AsRef<Path>>(
+        &self,
+        commit: S,
+        path: P,
+    ) -> Result<impl Read> {
+        match self.file_at_commit(commit.as_ref(), path.as_ref(), false)? {
+            Some(content) => Ok(Cursor::new(content)),
+            None => Err(eyre!(
+                "File not found at commit {}: {:?}",
+                commit.as_ref(),
+                path.as_ref()
+            )),
+        }
+    }
+
+    /// Returns the contents of the file at `path` in `commit`.
+    /// Returns `None` if `path` doesn't exist in `commit`, or isn't a file.
+    ///
+    /// Git stores a symlink as a blob containing the link target. If `resolve_symlinks` is `false`,
+    /// that target is returned as-is. If `true`, the link is followed within the commit's tree, and
+    /// `None` is returned if the target is missing or points outside the repo.
+    pub fn file_at_commit<S: AsRef<str>, P: AsRef<Path>>(
+        &self,
+        commit: S,
+        path: P,
+        resolve_symlinks: bool,
+    ) -> Result<Option<Vec<u8>>> {
+        // Same as the kernel's limit before returning ELOOP
+        const MAX_SYMLINK_HOPS: usize = 40;
+
+        let repo = self.to_repo().to_repository()?;
+
+        let tree = Self::commit_from_repository(&repo, commit.as_ref())?.tree()?;
+
+        let mut path = path.as_ref().to_path_buf();
+
+        for _ in 0..MAX_SYMLINK_HOPS {
+            let entry = match tree.get_path(&path) {
+                Ok(entry) => entry,
+                Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+
+            let is_symlink =
+                TreeEntryKind::from_filemode(entry.filemode()) == Some(TreeEntryKind::Symlink);
+
+            let blob = if let Ok(blob) = entry.to_object(&repo)?.into_blob() {
+                blob
+            } else {
+                return Ok(None);
+            };
+
+            if !is_symlink || !resolve_symlinks {
+                return Ok(Some(blob.content().to_vec()));
+            }
+
+            let target = if let Ok(target) = std::str::from_utf8(blob.content()) {
+                target
+            } else {
+                return Err(eyre!("Symlink target not valid utf-8: {:?}", path));
+            };
+
+            debug!("Following symlink {:?} -> {:?}", &path, target);
+
+            path = if let Some(p) = resolve_symlink_target(&path, Path::new(target)) {
+                p
+            } else {
+                // The link points outside of the repo
+                return Ok(None);
+            };
+        }
+
+        Err(eyre!("Too many levels of symbolic links: {:?}", path))
+    }
+
+    /// Returns the `RefMeta` of every ref in the repo, including `HEAD` and remote-tracking branches.
+    /// Symbolic refs report the name of the ref they point to, rather than resolving it
+    pub fn list_all_refs(&self) -> Result<Vec<RefMeta>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let mut refs = Vec::new();
+
+        // `HEAD` lives outside of `refs/`, so it isn't included by `references()`
+        match repo.find_reference("HEAD") {
+            Ok(head) => refs.push(RefMeta::from(&head)),
+            Err(e) if e.code() == ErrorCode::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        for reference in repo.references()? {
+            refs.push(RefMeta::from(&reference?));
+        }
+
+        Ok(refs)
+    }
+
+    /// Returns the value of `key` in the metadata attached to `commit` with `GitRepoMut::set_commit_metadata()`,
+    /// or `None` if it isn't set
+    pub fn get_commit_metadata<S: AsRef<str>>(
+        &self,
+        commit: S,
+        key: &str,
+    ) -> Result<Option<String>> {
+        let repo = self.to_repo().to_repository()?;
+        let commit = Self::commit_from_repository(&repo, commit.as_ref())?;
+
+        Ok(Self::commit_metadata_from_repository(&repo, commit.id())?
+            .into_iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v))
+    }
+
+    /// Parse the `key=value` lines of the `refs/notes/git-meta` note on `commit`, in order
+    pub(crate) fn commit_metadata_from_repository(
+        r: &Repository,
+        commit: Oid,
+    ) -> Result<Vec<(String, String)>> {
+        let note = match r.find_note(Some(COMMIT_METADATA_NOTES_REF), commit) {
+            Ok(note) => note,
+            Err(e) if e.code() == ErrorCode::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let message = String::from_utf8_lossy(note.message_bytes()).to_string();
+
+        Ok(message
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect())
+    }
+
+    /// Returns the local tags of the repo, and the commits they point at.
+    /// Providing a `pattern` will only return tags whose names match the glob, like `v1.*`.
+    /// Tags that don't point at a commit (e.g. a tag of a tree) are skipped
+    pub fn list_tags(&self, pattern: Option<&str>) -> Result<Vec<TagMeta>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let mut tags = Vec::new();
+
+        let tag_names = repo.tag_names(pattern)?;
+
+        for (name, bytes) in tag_names.iter().zip(tag_names.iter_bytes()) {
+            let name = if let Some(name) = name {
+                name
+            } else {
+                // We can't look up a tag by a lossy name, so skip it rather than failing the listing
+                warn!(
+                    "Skipping tag with name not valid utf-8: {:?}",
+                    String::from_utf8_lossy(bytes)
+                );
+                continue;
+            };
+
+            if let Some(tag) = Self::tag_meta_from_repository(&repo, name)? {
+                tags.push(tag);
+            }
+        }
+
+        Ok(tags)
+    }
+
+    /// Returns the tags on commits reachable from `to` but not from `from`, like the tags in `git log from..to`.
+    /// Tags are ordered by their commit, newest first, and tags of the same commit are ordered by name.
+    /// Both can be anything `git rev-parse` accepts, like a tag or commit id
+    pub fn tags_in_range<S: AsRef<str>>(&self, from: S, to: S) -> Result<Vec<TagMeta>> {
+        let positions: HashMap<String, usize> = self
+            .commits_unique_to(to.as_ref(), from.as_ref())?
+            .into_iter()
+            .enumerate()
+            .map(|(position, commit)| (commit.id, position))
+            .collect();
+
+        let mut tags: Vec<(usize, TagMeta)> = self
+            .list_tags(None)?
+            .into_iter()
+            .filter_map(|tag| positions.get(&tag.target.id).map(|p| (*p, tag)))
+            .collect();
+
+        tags.sort_by(|(a_position, a), (b_position, b)| {
+            a_position.cmp(b_position).then_with(|| a.name.cmp(&b.name))
+        });
+
+        Ok(tags.into_iter().map(|(_, tag)| tag).collect())
+    }
+
+    /// Returns the `GitCommitMeta` of the commit `tag` points at.
+    /// Annotated tags are followed through the tag object to their commit
+    pub fn tag_target(&self, tag: &str) -> Result<GitCommitMeta> {
+        let repo = self.to_repo().to_repository()?;
+
+        // Look up by full ref name, so a branch with the same name isn't picked instead
+        let commit = repo
+            .revparse_single(&format!("refs/tags/{}", tag))
+            .wrap_err(format!("Tag not found: {}", tag))?
+            .peel_to_commit()
+            .wrap_err(format!("Tag does not point at a commit: {}", tag))?;
+
+        Ok(GitCommitMeta::from(&commit))
+    }
+
+    /// Returns the committer time of the `HEAD` commit, in `Utc`. Detached HEAD is handled the same as a branch.
+    /// Useful for cache keys, without building a whole `GitCommitMeta`
+    pub fn head_timestamp(&self) -> Result<DateTime<Utc>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let commit = repo
+            .head()
+            .wrap_err("Could not get HEAD ref")?
+            .peel_to_commit()
+            .wrap_err("HEAD does not point at a commit")?;
+
+        Utc.timestamp_opt(commit.time().seconds(), 0)
+            .single()
+            .wrap_err("HEAD commit time is out of range")
+    }
+
+    /// Returns the names of all tags pointing at the current `HEAD` commit, like `git tag --points-at HEAD`.
+    /// Annotated tags are compared by the commit they point at.
+    /// An empty `Vec` means `HEAD` is not tagged
+    pub fn head_tags(&self) -> Result<Vec<String>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let head = repo
+            .head()
+            .wrap_err("Could not get HEAD ref")?
+            .peel_to_commit()
+            .wrap_err("Could not convert to commit")?;
+
+        let mut tags = Vec::new();
+
+        let tag_names = repo.tag_names(None)?;
+
+        for (name, bytes) in tag_names.iter().zip(tag_names.iter_bytes()) {
+            let name = if let Some(name) = name {
+                name
+            } else {
+                warn!(
+                    "Skipping tag with name not valid utf-8: {:?}",
+                    String::from_utf8_lossy(bytes)
+                );
+                continue;
+            };
+
+            let tag_commit = repo
+                .find_reference(&format!("refs/tags/{}", name))?
+                .peel_to_commit();
+
+            // Skip tags that don't point at commits
+            if let Ok(commit) = tag_commit {
+                if commit.id() == head.id() {
+                    tags.push(name.to_string());
+                }
+            }
+        }
+
+        Ok(tags)
+    }
+
+    /// Build the `TagMeta` for tag `name`, using an already opened repo.
+    /// Returns `None` if the tag doesn't point at a commit
+    fn tag_meta_from_repository(r: &Repository, name: &str) -> Result<Option<TagMeta>> {
+        let oid = r
+            .find_reference(&format!("refs/tags/{}", name))?
+            .resolve()?
+            .target()
+            .wrap_err(format!("Unable to resolve tag {}", name))?;
+
+        // Don't peel yet, so we can tell if this is an annotated tag object
+        let object = r.find_object(oid, None)?;
+
+        let (annotated, tagger, message) = if let Some(tag) = object.as_tag() {
+            (
+                true,
+                tag.tagger().map(|t| GitSignature::from(&t)),
+                tag.message().map(|m| m.to_string()),
+            )
+        } else {
+            (false, None, None)
+        };
+
+        let target = if let Ok(commit) = object.peel_to_commit() {
+            GitCommitMeta::from(&commit)
+        } else {
+            debug!("Skipping tag {} that doesn't point at a commit", name);
+            return Ok(None);
+        };
+
+        Ok(Some(TagMeta {
+            name: name.to_string(),
+            target,
+            annotated,
+            tagger,
+            message,
+        }))
+    }
+
+    /// Returns the paths of all files on the remote `branch`, without a full clone.
+    ///
+    /// The git protocol can't list files without fetching tree objects, so this still does a
+    /// minimal scratch clone (`--depth=1 --filter=tree:0 --no-checkout`), lists the tree with
+    /// `git ls-tree`, then removes the clone. No file contents are downloaded, but the trees are.
+    /// Requires the `git` CLI. Servers that don't allow filters will send the full commit.
+    pub fn remote_tree_listing(&self, branch: &str) -> Result<Vec<PathBuf>> {
+        UrlKind::require_remote(&self.url, "Listing a remote tree")?;
+
+        // Removed when dropped, including on error paths
+        let scratch_dir = self.new_scratch_dir()?;
+
+        let (clone_url, clone_config) = cli::authenticated_url(
+            &self.url,
+            &self.credentials,
+            &self.credential_provider,
+            self.host_key_policy,
+            &self.proxy,
+        )?;
+
+        let mut clone_command = cli::git_command(None);
+        clone_command
+            .arg("clone")
+            .arg(format!("{}", clone_url))
+            .arg(scratch_dir.as_path().as_os_str())
+            .arg("--depth=1")
+            .arg("--filter=tree:0")
+            .arg("--no-checkout")
+            .arg("--single-branch")
+            .arg("--branch")
+            .arg(branch);
+
+        for config in clone_config {
+            clone_command.arg("--config").arg(config);
+        }
+
+        cli::run(&mut clone_command).wrap_err("Failed to run git clone")?;
+
+        // Missing trees are fetched on demand by the git CLI, which git2 can't do
+        let listing = cli::run_git(
+            Some(scratch_dir.as_path()),
+            &["ls-tree", "-r", "-z", "--name-only", "HEAD"],
+        )
+        .wrap_err("Failed to list remote tree")?;
+
+        Ok(listing
+            .stdout
+            .split(|b| *b == b'\0')
+            .filter(|p| !p.is_empty())
+            .map(|p| PathBuf::from(String::from_utf8_lossy(p).into_owned()))
+            .collect())
+    }
+
+    /// Predict the result of merging `their_commit` into `HEAD`, without touching the working tree.
+    /// The merge is done in an in-memory index with `git2::Repository::merge_commits()`
+    pub fn merge_analysis<S: AsRef<str>>(&self, their_commit: S) -> Result<MergeAnalysis> {
+        let repo = self.to_repo().to_repository()?;
+
+        let ours = repo
+            .head()
+            .wrap_err("Could not get HEAD ref")?
+            .peel_to_commit()
+            .wrap_err("Could not convert to commit")?;
+        let theirs = Self::commit_from_repository(&repo, their_commit.as_ref())?;
+
+        let (analysis, _preference) =
+            repo.merge_analysis(&[&repo.find_annotated_commit(theirs.id())?])?;
+
+        let index = repo.merge_commits(&ours, &theirs, None)?;
+
+        let mut conflicts = Vec::new();
+
+        if index.has_conflicts() {
+            for conflict in index.conflicts()? {
+                let conflict = conflict?;
+
+                // Any side that still has the path will do, since a side may be missing (e.g. deleted)
+                if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+                    conflicts.push(PathBuf::from(
+                        String::from_utf8_lossy(&entry.path).to_string(),
+                    ));
+                }
+            }
+        }
+
+        Ok(MergeAnalysis {
+            fast_forward: analysis.is_fast_forward(),
+            conflicts,
+        })
+    }
+
+    /// Returns the untracked files in the working tree of the repo at `self.path`, like `git status`.
+    /// Files in untracked directories are listed individually.
+    /// Files ignored by `.gitignore` are left out, unless `include_ignored` is set
+    pub fn untracked_files(&self, include_ignored: bool) -> Result<Vec<PathBuf>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let mut options = git2::StatusOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .include_ignored(include_ignored)
+            .recurse_ignored_dirs(include_ignored);
+
+        let mut untracked = Vec::new();
+
+        for entry in repo.statuses(Some(&mut options))?.iter() {
+            let status = entry.status();
+
+            if status.is_wt_new() || status.is_ignored() {
+                untracked.push(PathBuf::from(
+                    String::from_utf8_lossy(entry.path_bytes()).to_string(),
+                ));
+            }
+        }
+
+        Ok(untracked)
+    }
+
+    /// Returns `true` if the working tree or index of the repo at `self.path` has changes from `HEAD`,
+    /// like `git describe --dirty`. Untracked files don't count as changes
+    pub fn is_dirty(&self) -> Result<bool> {
+        let repo = self.to_repo().to_repository()?;
+
+        let mut options = git2::StatusOptions::new();
+        options.include_untracked(false).include_ignored(false);
+
+        let statuses = repo.statuses(Some(&mut options))?;
+
+        Ok(!statuses.is_empty())
+    }
+
+    /// Returns `true` only if `HEAD` is at `commit` and `is_dirty()` is `false`, meaning the checkout is
+    /// exactly `commit` with no local changes. `commit` can be anything `git rev-parse` accepts
+    pub fn is_clean_at<S: AsRef<str>>(&self, commit: S) -> Result<bool> {
+        let repo = self.to_repo().to_repository()?;
+
+        let expected = Self::resolve_commit_id(&repo, commit.as_ref())?;
+
+        let head = repo
+            .head()
+            .wrap_err("Could not get HEAD ref")?
+            .peel_to_commit()
+            .wrap_err("HEAD does not point at a commit")?;
+
+        if head.id() != expected {
+            return Ok(false);
+        }
+
+        Ok(!self.is_dirty()?)
+    }
+
+    /// Returns a hash of the state of the repo at `self.path`, for use as a cache key.
+    /// The hash covers the `HEAD` commit id, every local branch head, and whether `is_dirty()`,
+    /// so moving any branch or changing a tracked file gives a different fingerprint.
+    /// It is the git blob id (SHA-1) of those lines, so identical checkouts always match
+    pub fn state_fingerprint(&self) -> Result<String> {
+        let repo = self.to_repo().to_repository()?;
+
+        let head = match repo.head() {
+            Ok(head) => head
+                .peel_to_commit()
+                .wrap_err("HEAD does not point at a commit")?
+                .id()
+                .to_string(),
+            // Nothing has been committed yet
+            Err(e) if e.code() == ErrorCode::UnbornBranch => String::new(),
+            Err(e) => return Err(e).wrap_err("Could not get HEAD ref"),
+        };
+
+        let mut branches = Vec::new();
+        for branch in repo.branches(Some(BranchType::Local))? {
+            let (branch, _) = branch?;
+            let reference = branch.get();
+
+            if let (Some(name), Some(id)) = (reference.name(), reference.target()) {
+                branches.push(format!("{} {}", name, id));
+            }
+        }
+        branches.sort();
+
+        let mut state = format!("HEAD {}\n", head);
+        for branch in branches {
+            state.push_str(&branch);
+            state.push('\n');
+        }
+        state.push_str(&format!("dirty {}\n", self.is_dirty()?));
+
+        Ok(Oid::hash_object(git2::ObjectType::Blob, state.as_bytes())?.to_string())
+    }
+
+    /// Returns `true` if the repo at `self.path` is a partial clone, like from `git clone --filter=blob:none`.
+    /// Partial clones are missing some objects, which git fetches from the remote the first time they're needed,
+    /// so blob-heavy operations may need network access. Detected by `extensions.partialClone`
+    /// or a `remote.<name>.promisor` config
+    pub fn is_partial_clone(&self) -> Result<bool> {
+        let repo = self.to_repo().to_repository()?;
+        let config = repo.config()?.snapshot()?;
+
+        if config.get_string("extensions.partialClone").is_ok() {
+            return Ok(true);
+        }
+
+        let mut promisors = Vec::new();
+        for entry in &config.entries(Some(r"remote\..*\.promisor"))? {
+            if let Some(name) = entry?.name() {
+                promisors.push(name.to_string());
+            }
+        }
+
+        Ok(promisors
+            .iter()
+            .any(|name| config.get_bool(name).unwrap_or(false)))
+    }
+
+    /// Returns `true` if `path` matches git's ignore rules, from `.gitignore` files, `.git/info/exclude`
+    /// and `core.excludesFile`, like `git check-ignore --no-index`. `path` is relative to the repo root,
+    /// and doesn't need to exist. Tracked files are only checked against the rules, so they can be ignored too
+    pub fn is_path_ignored<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        let repo = self.to_repo().to_repository()?;
+
+        repo.is_path_ignored(path.as_ref())
+            .wrap_err(format!("Could not check if {:?} is ignored", path.as_ref()))
+    }
+
+    /// Returns the operation in progress in the repo at `self.path`, like a merge or rebase.
+    /// Check for `RepoState::Clean` before making changes to a working copy
+    pub fn repo_state(&self) -> Result<RepoState> {
+        let repo = self.to_repo().to_repository()?;
+
+        Ok(repo.state().into())
+    }
+
+    /// Returns the name and status of each submodule of the repo at `self.path`, like `git submodule status`.
+    /// Use this to decide whether a submodule needs `git submodule update --init` before a build
+    pub fn submodule_status(&self) -> Result<Vec<(String, SubmoduleStatus)>> {
+        let repo = self.to_repo().to_repository()?;
+
+        let mut statuses = Vec::new();
+
+        for submodule in repo.submodules()? {
+            let name = String::from_utf8_lossy(submodule.name_bytes()).to_string();
+
+            let status = repo
+                .submodule_status(&name, git2::SubmoduleIgnore::None)
+                .wrap_err(format!("Failed to get status of submodule {}", name))?;
+
+            statuses.push((name, status.into()));
+        }
+
+        Ok(statuses)
+    }
+
+    /// Returns the on-disk size of the repo's objects, by summing the sizes of the files under `objects/`.
+    /// Pack object counts are read from the pack indexes. Useful for deciding when to repack or prune
+    pub fn repo_size(&self) -> Result<RepoSize> {
+        let repo = self.to_repo().to_repository()?;
+
+        let objects_dir = common_gitdir(&repo)?.join("objects");
+
+        let mut size = RepoSize::default();
+
+        for dir in std::fs::read_dir(&objects_dir)? {
+            let dir = dir?;
+            let name = dir.file_name();
+            let name = name.to_string_lossy();
+
+            if name == "pack" {
+                for file in std::fs::read_dir(dir.path())? {
+                    let file = file?;
+                    size.packed_size_bytes += file.metadata()?.len();
+
+                    if file.path().extension().is_some_and(|ext| ext == "idx") {
+                        size.object_count += pack_index_object_count(&file.path())?;
+                    }
+                }
+            } else if name.len() == 2 && name.chars().all(|c| c.is_ascii_hexdigit()) {
+                // Loose objects are stored in directories named by the first 2 hex chars of their id
+                for file in std::fs::read_dir(dir.path())? {
+                    size.object_count += 1;
+                    size.loose_size_bytes += file?.metadata()?.len();
+                }
+            }
+        }
+
+        Ok(size)
+    }
+
+    /// Returns `true` if the repo has a commit-graph file, either as a single file or as a split chain.
+    /// A commit-graph speeds up ancestry walks, like `GitRepoMut::branch_relationship()`
+    pub fn has_commit_graph(&self) -> Result<bool> {
+        let repo = self.to_repo().to_repository()?;
+
+        let info_dir = common_gitdir(&repo)?.join("objects").join("info");
+
+        Ok(info_dir.join("commit-graph").is_file()
+            || info_dir
+                .join("commit-graphs")
+                .join("commit-graph-chain")
+                .is_file())
+    }
+
+    /// Check if new commits exist by performing a shallow clone and comparing branch heads
     pub fn new_commits_exist(&self) -> Result<bool> {
         // Let's do a shallow clone behind the scenes using the same branch and creds
         let repo = if let Ok(gitrepo) = GitRepo::new(self.url.to_string()) {
@@ -442,22 +2656,21 @@ impl GitRepoInfo {
                 return Err(eyre!("No branch set"));
             };
 
-            gitrepo
+            let mut gitrepo = gitrepo
                 .with_branch(Some(branch))
-                .with_credentials(self.credentials.clone())
+                .with_credentials(self.credentials.clone());
+            gitrepo.credential_provider = self.credential_provider.clone();
+            gitrepo
         } else {
             return Err(eyre!("Could not crete new GitUrl"));
         };
 
-        let tempdir = if let Ok(dir) = Temp::new_dir() {
-            dir
-        } else {
-            return Err(eyre!("Could not create temporary dir"));
-        };
+        // Removed when dropped, including on error paths
+        let tempdir = self.new_scratch_dir()?;
 
         // We can do a shallow clone, because we only want the newest history
         let clone: GitRepoCloneRequest = repo.into();
-        let repo = if let Ok(gitrepo) = clone.git_clone_shallow(tempdir) {
+        let repo = if let Ok(gitrepo) = clone.git_clone_shallow(&tempdir) {
             gitrepo
         } else {
             return Err(eyre!("Could not shallow clone dir"));
@@ -467,97 +2680,237 @@ impl GitRepoInfo {
         Ok(self.head != repo.head)
     }
 
-    /// Builds a `git2::RemoteCallbacks` using `self.credentials` to be used
+    /// Convert into a `GitRepoMut`, for operations that modify the repo
+    pub fn into_mut(self) -> GitRepoMut {
+        GitRepoMut { info: self }
+    }
+
+    /// Returns the `CredentialConfig` of `self`, which builds the same callbacks as
+    /// `build_git2_remotecallback()` as many times as needed
+    pub fn credential_config(&self) -> CredentialConfig {
+        CredentialConfig {
+            url: self.url.clone(),
+            // The provider is used instead of the credentials when both are set
+            credentials: if self.credential_provider.is_some() {
+                None
+            } else {
+                self.credentials.clone()
+            },
+            credential_provider: self.credential_provider.clone(),
+            host_key_policy: self.host_key_policy,
+        }
+    }
+
+    /// Builds a `git2::RemoteCallbacks` using `self.credential_provider`, or `self.credentials` to be used
     /// in authenticated calls to a remote repo
-    pub fn build_git2_remotecallback(&self) -> Result<git2::RemoteCallbacks> {
-        if let Some(cred) = self.credentials.clone() {
-            debug!("Before building callback: {:?}", &cred);
-
-            match cred {
-                GitCredentials::SshKey {
-                    username,
-                    public_key,
-                    private_key,
-                    passphrase,
-                } => {
-                    let mut cb = git2::RemoteCallbacks::new();
-
-                    cb.credentials(
-                        move |_, _, _| match (public_key.clone(), passphrase.clone()) {
-                            (None, None) => {
-                                let key = if let Ok(key) =
-                                    Cred::ssh_key(&username, None, private_key.as_path(), None)
-                                {
-                                    key
-                                } else {
-                                    return Err(git2::Error::from_str(
-                                        "Could not create credentials object for ssh key",
-                                    ));
-                                };
-                                Ok(key)
-                            }
-                            (None, Some(pp)) => {
-                                let key = if let Ok(key) = Cred::ssh_key(
-                                    &username,
-                                    None,
-                                    private_key.as_path(),
-                                    Some(pp.as_ref()),
-                                ) {
-                                    key
-                                } else {
-                                    return Err(git2::Error::from_str(
-                                        "Could not create credentials object for ssh key",
-                                    ));
-                                };
-                                Ok(key)
-                            }
-                            (Some(pk), None) => {
-                                let key = if let Ok(key) = Cred::ssh_key(
-                                    &username,
-                                    Some(pk.as_path()),
-                                    private_key.as_path(),
-                                    None,
-                                ) {
-                                    key
-                                } else {
-                                    return Err(git2::Error::from_str(
-                                        "Could not create credentials object for ssh key",
-                                    ));
-                                };
-                                Ok(key)
-                            }
-                            (Some(pk), Some(pp)) => {
-                                let key = if let Ok(key) = Cred::ssh_key(
-                                    &username,
-                                    Some(pk.as_path()),
-                                    private_key.as_path(),
-                                    Some(pp.as_ref()),
-                                ) {
-                                    key
-                                } else {
-                                    return Err(git2::Error::from_str(
-                                        "Could not create credentials object for ssh key",
-                                    ));
-                                };
-                                Ok(key)
-                            }
-                        },
-                    );
-
-                    Ok(cb)
-                }
-                GitCredentials::UserPassPlaintext { username, password } => {
-                    let mut cb = git2::RemoteCallbacks::new();
-                    cb.credentials(move |_, _, _| {
-                        Cred::userpass_plaintext(username.as_str(), password.as_str())
-                    });
+    pub fn build_git2_remotecallback(&self) -> Result<git2::RemoteCallbacks<'_>> {
+        remote_callbacks(
+            &self.url,
+            &self.credentials,
+            &self.credential_provider,
+            self.host_key_policy,
+        )
+    }
+}
+
+impl CredentialConfig {
+    /// Builds a fresh `git2::RemoteCallbacks` to authenticate with, for one network operation
+    pub fn callbacks(&self) -> Result<git2::RemoteCallbacks<'_>> {
+        remote_callbacks(
+            &self.url,
+            &self.credentials,
+            &self.credential_provider,
+            self.host_key_policy,
+        )
+    }
+}
+
+/// Builds a `git2::RemoteCallbacks` using `credential_provider`, or `credentials`, and checks
+/// the host key of ssh urls with `host_key_policy`
+fn remote_callbacks<'a>(
+    url: &GitUrl,
+    credentials: &'a Option<GitCredentials>,
+    credential_provider: &'a Option<SharedCredentialProvider>,
+    host_key_policy: HostKeyPolicy,
+) -> Result<git2::RemoteCallbacks<'a>> {
+    let is_ssh = matches!(url.scheme, Scheme::Ssh | Scheme::GitSsh);
+
+    // git2 only gives us a hash of the host key, so a new key can't be recorded for the next connection.
+    // Accepting it anyway would accept any unknown host every time, like `AcceptAll`
+    if is_ssh && host_key_policy == HostKeyPolicy::AcceptNew {
+        return Err(eyre!(
+            "HostKeyPolicy::AcceptNew isn't supported by git2 operations, which can't record new host keys. \
+             Use git_clone_shallow(), which connects with the git CLI, or add the host to known_hosts"
+        ));
+    }
+
+    let mut cb = if let Some(provider) = credential_provider {
+        provider.0.remote_callbacks()?
+    } else if let Some(cred) = credentials {
+        // Otherwise this fails later, inside of the fetch, with a cryptic error
+        if matches!(cred, GitCredentials::SshKey { .. }) && !git2::Version::get().ssh() {
+            return Err(eyre!(
+                "An ssh key was given, but git2 was built without ssh support. \
+                 Use git_clone_shallow(), which clones with the git CLI, or rebuild git2 with its `ssh` feature"
+            ));
+        }
+
+        debug!("Before building callback: {:?}", cred);
+        cred.remote_callbacks()?
+    } else {
+        // No credentials. Repo is public
+        git2::RemoteCallbacks::new()
+    };
+
+    // Only for ssh, since git2 doesn't tell the callback whether a TLS certificate is valid.
+    // Without a callback, libssh2 connects without checking the host key at all
+    if is_ssh && host_key_policy != HostKeyPolicy::AcceptAll {
+        let port = url.port.filter(|p| *p != 22);
+
+        cb.certificate_check(move |cert, host| {
+            let host = match port {
+                Some(port) => format!("[{}]:{}", host, port),
+                None => host.to_string(),
+            };
+
+            check_host_key(cert, &host)
+        });
+    }
+
+    Ok(cb)
+}
+
+/// Connect to `url` with a detached remote, and return the name and id of every ref it advertises
+fn list_remote_heads(
+    url: &str,
+    credential_config: &CredentialConfig,
+    proxy: &ProxyConfig,
+) -> Result<Vec<(String, Oid)>> {
+    let mut remote = git2::Remote::create_detached(url)?;
+
+    let connection = remote
+        .connect_auth(
+            git2::Direction::Fetch,
+            Some(credential_config.callbacks()?),
+            proxy.to_git2(),
+        )
+        .wrap_err("Unable to connect to git repo")?;
+
+    Ok(connection
+        .list()?
+        .iter()
+        .map(|head| (head.name().to_string(), head.oid()))
+        .collect())
+}
+
+/// Returns whether to connect to `host`, by checking its key in `cert` against `known_hosts`
+fn check_host_key(cert: &git2::cert::Cert, host: &str) -> bool {
+    let fingerprint = if let Some(hash) = cert.as_hostkey().and_then(|key| key.hash_sha256()) {
+        cli::sha256_fingerprint(hash)
+    } else {
+        warn!("No SHA256 host key for {}. Refusing to connect", host);
+        return false;
+    };
+
+    match cli::known_host_fingerprints(host) {
+        Ok(known) if known.is_empty() => {
+            warn!("Host key for {} not found in known_hosts", host);
+            false
+        }
+        Ok(known) => {
+            let matched = known.contains(&fingerprint);
+            if !matched {
+                warn!(
+                    "Host key {} for {} doesn't match known_hosts",
+                    fingerprint, host
+                );
+            }
+            matched
+        }
+        Err(e) => {
+            warn!("Unable to check known_hosts for {}: {}", host, e);
+            false
+        }
+    }
+}
 
-                    Ok(cb)
+/// Resolve a symlink `target` relative to the directory containing `link`.
+/// Both are relative to the repo root. Returns `None` if the target is absolute,
+/// or if it escapes the repo root
+fn resolve_symlink_target(link: &Path, target: &Path) -> Option<PathBuf> {
+    let mut resolved = PathBuf::new();
+
+    let base = link.parent().unwrap_or_else(|| Path::new(""));
+
+    for component in base.components().chain(target.components()) {
+        match component {
+            Component::Normal(c) => resolved.push(c),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    return None;
                 }
             }
-        } else {
-            // No credentials. Repo is public
-            Ok(git2::RemoteCallbacks::new())
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(resolved)
+}
+
+/// Returns the directory holding the objects and refs shared by all worktrees of `r`.
+/// For linked worktrees, this is read from the `commondir` file in the worktree's gitdir
+fn common_gitdir(r: &Repository) -> Result<PathBuf> {
+    let gitdir = r.path();
+
+    match std::fs::read_to_string(gitdir.join("commondir")) {
+        Ok(commondir) => Ok(gitdir.join(commondir.trim_end())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(gitdir.to_path_buf()),
+        Err(e) => Err(e).wrap_err("Could not read commondir"),
+    }
+}
+
+/// Returns the commits at the boundary of a shallow clone, whose parents are missing.
+/// Returns an empty list if `r` is not shallow
+fn shallow_commits(r: &Repository) -> Result<Vec<Oid>> {
+    match std::fs::read_to_string(common_gitdir(r)?.join("shallow")) {
+        Ok(contents) => contents
+            .lines()
+            .map(|line| Ok(Oid::from_str(line.trim())?))
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).wrap_err("Could not read shallow file"),
+    }
+}
+
+/// Convert a ref, branch or remote name to a `String`, replacing invalid utf-8 with `U+FFFD`.
+/// Logs a warning naming the `kind` of name if any replacement was needed
+fn utf8_lossy(bytes: &[u8], kind: &str) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(name) => name.to_string(),
+        Err(_) => {
+            let lossy = String::from_utf8_lossy(bytes).to_string();
+            warn!("{} name not valid utf-8, using {:?}", kind, lossy);
+            lossy
         }
     }
 }
+
+/// Returns the number of objects in a pack, read from the last entry of the pack index's fanout table
+fn pack_index_object_count(idx: &Path) -> Result<usize> {
+    let contents = std::fs::read(idx).wrap_err(format!("Could not read pack index {:?}", idx))?;
+
+    // Version 2+ indexes start with a magic number and version. Version 1 starts with the fanout table
+    let fanout = if contents.starts_with(b"\xfftOc") {
+        8
+    } else {
+        0
+    };
+    let last = fanout + 255 * 4;
+
+    let count = contents
+        .get(last..last + 4)
+        .wrap_err(format!("Pack index too short: {:?}", idx))?;
+
+    Ok(u32::from_be_bytes([count[0], count[1], count[2], count[3]]) as usize)
+}
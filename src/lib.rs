@@ -41,28 +41,59 @@
 //! *Note:* Shallow cloning requires `git` CLI to be installed
 
 use chrono::prelude::*;
-use color_eyre::eyre::Report;
-use git2::Repository;
+use color_eyre::eyre::{eyre, Report, Result};
+use git2::{Cred, Repository};
+use git_url_parse::{GitUrl, Scheme};
 use hex::ToHex;
 
+mod cli;
 #[doc(hidden)]
 pub mod clone;
 #[doc(hidden)]
 pub mod info;
 #[doc(hidden)]
+pub mod message;
+#[doc(hidden)]
 pub mod types;
 
 #[doc(hidden)]
 pub mod repo;
 
-//// Can I use this as an empty trait for trait objects
+#[doc(hidden)]
+pub mod repo_mut;
+
+// Can I use this as an empty trait for trait objects
 //pub trait GitInfo {}
-//
 
 // Re-export our types in the root
 #[doc(inline)]
 pub use crate::types::*;
 
+#[doc(inline)]
+pub use crate::message::validate_message;
+
+impl std::fmt::Display for GitMetaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GitMetaError::ConnectTimeout { url, timeout } => {
+                write!(f, "Timed out after {:?} connecting to {}", timeout, url)
+            }
+            GitMetaError::ObjectLimitExceeded {
+                max_objects,
+                total_objects,
+            } => {
+                write!(
+                    f,
+                    "Clone aborted: {} objects to transfer exceeds the limit of {}",
+                    total_objects, max_objects
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for GitMetaError {}
+
 impl GitCommitMeta {
     /// Trait bound for `id` is to convert the output from:
     /// `git2::Commit.id().as_bytes()` into a `String`
@@ -71,16 +102,44 @@ impl GitCommitMeta {
             id: hex::encode(id),
             message: None,
             timestamp: None,
+            author_timestamp: None,
+            committer_timestamp: None,
+            signed: None,
         }
     }
 
+    /// Create from a commit id that is already a hex string, like the output of `git rev-parse`.
+    /// Returns `Err` if `id` is not a full 40-character hex id. The id is stored lowercase
+    pub fn from_id_str(id: impl Into<String>) -> Result<GitCommitMeta> {
+        let id = id.into();
+
+        if id.len() != 40 || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(eyre!("Not a full 40-character hex commit id: {}", id));
+        }
+
+        Ok(GitCommitMeta {
+            id: id.to_ascii_lowercase(),
+            message: None,
+            timestamp: None,
+            author_timestamp: None,
+            committer_timestamp: None,
+            signed: None,
+        })
+    }
+
     /// `time` is intended to convert output from:
-    /// `git2::Commit.time().seconds()` into `Datetime<Utc>`
+    /// `git2::Commit.time().seconds()` into `Datetime<Utc>`.
+    /// Sets both `timestamp` and `committer_timestamp`
     pub fn with_timestamp(mut self, time: i64) -> Self {
-        let naive_datetime = NaiveDateTime::from_timestamp(time, 0);
-        let datetime: DateTime<Utc> = DateTime::from_utc(naive_datetime, Utc);
+        self.timestamp = Utc.timestamp_opt(time, 0).single();
+        self.committer_timestamp = self.timestamp;
+        self
+    }
 
-        self.timestamp = Some(datetime);
+    /// `time` is intended to convert output from:
+    /// `git2::Commit.author().when().seconds()` into `Datetime<Utc>`
+    pub fn with_author_timestamp(mut self, time: i64) -> Self {
+        self.author_timestamp = Utc.timestamp_opt(time, 0).single();
         self
     }
 
@@ -89,13 +148,467 @@ impl GitCommitMeta {
         self.message = msg;
         self
     }
+
+    /// Best-effort guess of the pull request (or merge request) number this commit merged in, from
+    /// its message. Recognizes GitHub merge subjects (`Merge pull request #123 from ...`),
+    /// squash-merge subjects (`Add feature (#123)`), and GitLab merge trailers (`See merge request group/project!123`).
+    /// Returns `None` if no pattern matches
+    pub fn merge_pr_number(&self) -> Option<u64> {
+        let message = self.message.as_deref()?;
+        let subject = message.lines().next().unwrap_or_default().trim_end();
+
+        if let Some(rest) = subject.strip_prefix("Merge pull request #") {
+            return rest.split_whitespace().next()?.parse().ok();
+        }
+
+        if let Some((_, number)) = subject.strip_suffix(')').and_then(|s| s.rsplit_once("(#")) {
+            if let Ok(number) = number.parse() {
+                return Some(number);
+            }
+        }
+
+        message
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("See merge request "))
+            .find_map(|reference| {
+                let (_project, number) = reference.rsplit_once('!')?;
+                number.parse::<u64>().ok()
+            })
+    }
+}
+
+impl GitDiffOptions {
+    /// Create `GitDiffOptions` with the same defaults as plain `git diff`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of unchanged lines around each hunk
+    pub fn with_context_lines(mut self, lines: u32) -> Self {
+        self.context_lines = Some(lines);
+        self
+    }
+
+    /// Set the max number of unchanged lines between hunks before they are merged
+    pub fn with_interhunk_lines(mut self, lines: u32) -> Self {
+        self.interhunk_lines = Some(lines);
+        self
+    }
+
+    /// Only include changes to paths matching `pathspec`
+    pub fn with_pathspec(mut self, pathspec: Vec<String>) -> Self {
+        self.pathspec = pathspec;
+        self
+    }
+
+    /// Leave out changes to paths matching `exclude`
+    pub fn with_exclude(mut self, exclude: Vec<String>) -> Self {
+        self.exclude = exclude;
+        self
+    }
+
+    /// Ignore changes in whitespace
+    pub fn with_ignore_whitespace(mut self, ignore: bool) -> Self {
+        self.ignore_whitespace = ignore;
+        self
+    }
+
+    /// Returns a `git2::Pathspec` matching `self.exclude`, or `None` if nothing is excluded
+    pub(crate) fn exclude_pathspec(&self) -> Result<Option<git2::Pathspec>> {
+        if self.exclude.is_empty() {
+            return Ok(None);
+        }
+
+        let excludes = self.exclude.iter().map(|e| {
+            e.strip_prefix(":!")
+                .or_else(|| e.strip_prefix(":(exclude)"))
+                .unwrap_or(e)
+        });
+
+        Ok(Some(git2::Pathspec::new(excludes)?))
+    }
+
+    /// Returns a `git2::DiffOptions` with only the options that were set
+    pub(crate) fn to_git2(&self) -> git2::DiffOptions {
+        let mut opts = git2::DiffOptions::new();
+
+        for path in &self.pathspec {
+            opts.pathspec(path);
+        }
+
+        if let Some(lines) = self.context_lines {
+            opts.context_lines(lines);
+        }
+
+        if let Some(lines) = self.interhunk_lines {
+            opts.interhunk_lines(lines);
+        }
+
+        if self.ignore_whitespace {
+            opts.ignore_whitespace(true);
+        }
+
+        opts
+    }
+}
+
+impl MessageRules {
+    /// Create `MessageRules` that only require a non-empty subject
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the max number of characters in the subject line
+    pub fn with_max_subject_length(mut self, max: usize) -> Self {
+        self.max_subject_length = Some(max);
+        self
+    }
+
+    /// Require a body after the subject
+    pub fn with_require_body(mut self, require: bool) -> Self {
+        self.require_body = require;
+        self
+    }
+
+    /// Require a conventional commit type from `types`
+    pub fn with_allowed_types(mut self, types: Vec<String>) -> Self {
+        self.allowed_types = types;
+        self
+    }
+}
+
+impl BranchHeadsDiff {
+    /// Compare two sets of branch heads. Branches are considered the same if their HEAD commit ids match
+    pub fn between(ours: &BranchHeads, theirs: &BranchHeads) -> Self {
+        let mut diff = BranchHeadsDiff::default();
+
+        for (branch, our_head) in ours {
+            match theirs.get(branch) {
+                Some(their_head) if their_head.id != our_head.id => {
+                    diff.changed
+                        .insert(branch.clone(), (our_head.clone(), their_head.clone()));
+                }
+                Some(_) => {}
+                None => {
+                    diff.only_in_ours.insert(branch.clone(), our_head.clone());
+                }
+            }
+        }
+
+        for (branch, their_head) in theirs {
+            if !ours.contains_key(branch) {
+                diff.only_in_theirs
+                    .insert(branch.clone(), their_head.clone());
+            }
+        }
+
+        diff
+    }
+
+    /// Returns `true` if both sides have the same branches, pointing at the same commits
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.only_in_ours.is_empty() && self.only_in_theirs.is_empty()
+    }
+}
+
+impl ProxyConfig {
+    /// Returns the `git2::ProxyOptions` for this config, or `None` to leave git2's default of no proxy
+    pub(crate) fn to_git2(&self) -> Option<git2::ProxyOptions<'_>> {
+        let mut options = git2::ProxyOptions::new();
+
+        match self {
+            ProxyConfig::None => return None,
+            ProxyConfig::Auto => options.auto(),
+            ProxyConfig::Url(url) => options.url(url),
+        };
+
+        Some(options)
+    }
+
+    /// Returns the `-c` config for the `git` CLI to use this proxy, if any.
+    /// The CLI already detects the proxy itself, so only `ProxyConfig::Url` needs config
+    pub(crate) fn cli_config(&self) -> Option<String> {
+        match self {
+            ProxyConfig::Url(url) => Some(format!("http.proxy={}", url)),
+            ProxyConfig::None | ProxyConfig::Auto => None,
+        }
+    }
+}
+
+impl From<&git2::Commit<'_>> for GitCommitMeta {
+    /// Convert from `&git2::Commit` to `GitCommitMeta`
+    fn from(commit: &git2::Commit<'_>) -> Self {
+        GitCommitMeta::new(commit.id().as_bytes())
+            .with_timestamp(commit.committer().when().seconds())
+            .with_author_timestamp(commit.author().when().seconds())
+            .with_message(commit.message().map(|m| m.to_string()))
+    }
+}
+
+impl From<&git2::Signature<'_>> for GitSignature {
+    /// Convert from `&git2::Signature` to `GitSignature`
+    fn from(signature: &git2::Signature<'_>) -> Self {
+        GitSignature {
+            name: signature.name().map(|n| n.to_string()),
+            email: signature.email().map(|e| e.to_string()),
+            timestamp: Utc.timestamp_opt(signature.when().seconds(), 0).single(),
+        }
+    }
+}
+
+impl From<&GitUrl> for UrlKind {
+    /// Classify a `GitUrl` by its scheme. Paths without a scheme parse as `Scheme::File`
+    fn from(url: &GitUrl) -> Self {
+        match url.scheme {
+            Scheme::File => UrlKind::Local,
+            _ => UrlKind::Remote,
+        }
+    }
+}
+
+impl UrlKind {
+    /// Returns `Err` if `url` is not a remote url, naming the `operation` that requires one
+    pub(crate) fn require_remote(url: &GitUrl, operation: &str) -> Result<()> {
+        if UrlKind::from(url) == UrlKind::Local {
+            return Err(eyre!(
+                "{} requires a remote url, but {} is a local path",
+                operation,
+                url
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl CredentialProvider for GitCredentials {
+    /// Authenticate with the ssh key or user/pass
+    fn remote_callbacks(&self) -> Result<git2::RemoteCallbacks<'_>> {
+        match self.clone() {
+            GitCredentials::SshKey {
+                username,
+                public_key,
+                private_key,
+                passphrase,
+            } => {
+                let mut cb = git2::RemoteCallbacks::new();
+
+                cb.credentials(
+                    move |_, _, _| match (public_key.clone(), passphrase.clone()) {
+                        (None, None) => {
+                            let key = if let Ok(key) =
+                                Cred::ssh_key(&username, None, private_key.as_path(), None)
+                            {
+                                key
+                            } else {
+                                return Err(git2::Error::from_str(
+                                    "Could not create credentials object for ssh key",
+                                ));
+                            };
+                            Ok(key)
+                        }
+                        (None, Some(pp)) => {
+                            let key = if let Ok(key) = Cred::ssh_key(
+                                &username,
+                                None,
+                                private_key.as_path(),
+                                Some(pp.as_ref()),
+                            ) {
+                                key
+                            } else {
+                                return Err(git2::Error::from_str(
+                                    "Could not create credentials object for ssh key",
+                                ));
+                            };
+                            Ok(key)
+                        }
+                        (Some(pk), None) => {
+                            let key = if let Ok(key) = Cred::ssh_key(
+                                &username,
+                                Some(pk.as_path()),
+                                private_key.as_path(),
+                                None,
+                            ) {
+                                key
+                            } else {
+                                return Err(git2::Error::from_str(
+                                    "Could not create credentials object for ssh key",
+                                ));
+                            };
+                            Ok(key)
+                        }
+                        (Some(pk), Some(pp)) => {
+                            let key = if let Ok(key) = Cred::ssh_key(
+                                &username,
+                                Some(pk.as_path()),
+                                private_key.as_path(),
+                                Some(pp.as_ref()),
+                            ) {
+                                key
+                            } else {
+                                return Err(git2::Error::from_str(
+                                    "Could not create credentials object for ssh key",
+                                ));
+                            };
+                            Ok(key)
+                        }
+                    },
+                );
+
+                Ok(cb)
+            }
+            GitCredentials::UserPassPlaintext { username, password } => {
+                let mut cb = git2::RemoteCallbacks::new();
+                cb.credentials(move |_, _, _| {
+                    Cred::userpass_plaintext(username.as_str(), password.as_str())
+                });
+
+                Ok(cb)
+            }
+        }
+    }
+}
+
+impl GitCredentials {
+    /// Returns `GitCredentials` from the environment, for use in CI. Checked in order:
+    /// - `GIT_USERNAME` and `GIT_PASSWORD`, as `UserPassPlaintext`
+    /// - `GIT_TOKEN`, then `GITHUB_TOKEN`, as `UserPassPlaintext` with the token as the password.
+    ///   The username is `GIT_USERNAME` if set, otherwise `x-access-token`
+    ///
+    /// Empty variables are treated as unset. Returns `None` if no credentials are present
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+
+        let username = var("GIT_USERNAME");
+
+        if let (Some(username), Some(password)) = (username.clone(), var("GIT_PASSWORD")) {
+            return Some(GitCredentials::UserPassPlaintext { username, password });
+        }
+
+        let token = var("GIT_TOKEN").or_else(|| var("GITHUB_TOKEN"))?;
+
+        Some(GitCredentials::UserPassPlaintext {
+            username: username.unwrap_or_else(|| "x-access-token".to_string()),
+            password: token,
+        })
+    }
+}
+
+impl From<&git2::Reference<'_>> for RefMeta {
+    fn from(reference: &git2::Reference<'_>) -> Self {
+        let kind = if reference.is_branch() {
+            RefKind::Branch
+        } else if reference.is_remote() {
+            RefKind::RemoteBranch
+        } else if reference.is_tag() {
+            RefKind::Tag
+        } else if reference.is_note() {
+            RefKind::Note
+        } else {
+            RefKind::Other
+        };
+
+        let (target, is_symbolic) = match reference.symbolic_target_bytes() {
+            Some(target) => (String::from_utf8_lossy(target).to_string(), true),
+            None => (
+                reference
+                    .target()
+                    .map(|oid| oid.to_string())
+                    .unwrap_or_default(),
+                false,
+            ),
+        };
+
+        RefMeta {
+            name: String::from_utf8_lossy(reference.name_bytes()).to_string(),
+            kind,
+            target,
+            is_symbolic,
+        }
+    }
+}
+
+impl From<git2::RepositoryState> for RepoState {
+    fn from(state: git2::RepositoryState) -> Self {
+        match state {
+            git2::RepositoryState::Clean => RepoState::Clean,
+            git2::RepositoryState::Merge => RepoState::Merge,
+            git2::RepositoryState::Revert => RepoState::Revert,
+            git2::RepositoryState::RevertSequence => RepoState::RevertSequence,
+            git2::RepositoryState::CherryPick => RepoState::CherryPick,
+            git2::RepositoryState::CherryPickSequence => RepoState::CherryPickSequence,
+            git2::RepositoryState::Bisect => RepoState::Bisect,
+            git2::RepositoryState::Rebase => RepoState::Rebase,
+            git2::RepositoryState::RebaseInteractive => RepoState::RebaseInteractive,
+            git2::RepositoryState::RebaseMerge => RepoState::RebaseMerge,
+            git2::RepositoryState::ApplyMailbox => RepoState::ApplyMailbox,
+            git2::RepositoryState::ApplyMailboxOrRebase => RepoState::ApplyMailboxOrRebase,
+        }
+    }
+}
+
+impl From<git2::Delta> for Delta {
+    fn from(delta: git2::Delta) -> Self {
+        match delta {
+            git2::Delta::Unmodified => Delta::Unmodified,
+            git2::Delta::Added => Delta::Added,
+            git2::Delta::Deleted => Delta::Deleted,
+            git2::Delta::Modified => Delta::Modified,
+            git2::Delta::Renamed => Delta::Renamed,
+            git2::Delta::Copied => Delta::Copied,
+            git2::Delta::Ignored => Delta::Ignored,
+            git2::Delta::Untracked => Delta::Untracked,
+            git2::Delta::Typechange => Delta::Typechange,
+            git2::Delta::Unreadable => Delta::Unreadable,
+            git2::Delta::Conflicted => Delta::Conflicted,
+        }
+    }
+}
+
+impl From<git2::SubmoduleStatus> for SubmoduleStatus {
+    fn from(status: git2::SubmoduleStatus) -> Self {
+        SubmoduleStatus {
+            in_head: status.is_in_head(),
+            in_index: status.is_in_index(),
+            in_config: status.is_in_config(),
+            in_workdir: status.is_in_wd(),
+            index_added: status.is_index_added(),
+            index_deleted: status.is_index_deleted(),
+            index_modified: status.is_index_modified(),
+            wd_uninitialized: status.is_wd_uninitialized(),
+            wd_added: status.is_wd_added(),
+            wd_deleted: status.is_wd_deleted(),
+            wd_modified: status.is_wd_modified(),
+            wd_index_modified: status.contains(git2::SubmoduleStatus::WD_INDEX_MODIFIED),
+            wd_wd_modified: status.is_wd_wd_modified(),
+            wd_untracked: status.is_wd_untracked(),
+        }
+    }
+}
+
+impl TreeEntryKind {
+    /// Map a git tree entry filemode (e.g. `git2::TreeEntry.filemode()`) to a `TreeEntryKind`.
+    /// Returns `None` for filemodes git doesn't write
+    pub fn from_filemode(mode: i32) -> Option<Self> {
+        match mode {
+            0o100644 | 0o100664 => Some(TreeEntryKind::File),
+            0o100755 => Some(TreeEntryKind::Executable),
+            0o120000 => Some(TreeEntryKind::Symlink),
+            0o040000 => Some(TreeEntryKind::Directory),
+            0o160000 => Some(TreeEntryKind::Submodule),
+            _ => None,
+        }
+    }
 }
 
 impl TryFrom<Repository> for GitRepo {
     type Error = Report;
 
     fn try_from(repo: Repository) -> Result<Self, Self::Error> {
-        GitRepo::open(repo.path().to_path_buf(), None, None)
+        // The gitdir of a linked worktree is inside of the main repo's gitdir, so open the worktree itself.
+        // Bare repos have no worktree
+        let path = repo.workdir().unwrap_or_else(|| repo.path());
+        GitRepo::open(path.to_path_buf(), None, None)
     }
 }
 
@@ -106,6 +619,7 @@ impl From<&GitRepoInfo> for GitRepo {
             url: repo.url.clone(),
             head: repo.head.clone(),
             credentials: repo.credentials.clone(),
+            credential_provider: repo.credential_provider.clone(),
             branch: repo.branch.clone(),
             path: repo.path.clone(),
         }
@@ -119,6 +633,7 @@ impl From<&GitRepoCloneRequest> for GitRepo {
             url: repo.url.clone(),
             head: repo.head.clone(),
             credentials: repo.credentials.clone(),
+            credential_provider: repo.credential_provider.clone(),
             branch: repo.branch.clone(),
             path: repo.path.clone(),
         }
@@ -132,8 +647,10 @@ impl From<GitRepo> for GitRepoCloneRequest {
             url: repo.url.clone(),
             head: repo.head.clone(),
             credentials: repo.credentials.clone(),
+            credential_provider: repo.credential_provider.clone(),
             branch: repo.branch.clone(),
             path: repo.path,
+            ..Default::default()
         }
     }
 }
@@ -145,8 +662,10 @@ impl From<&GitRepo> for GitRepoCloneRequest {
             url: repo.url.clone(),
             head: repo.head.clone(),
             credentials: repo.credentials.clone(),
+            credential_provider: repo.credential_provider.clone(),
             branch: repo.branch.clone(),
             path: repo.path.clone(),
+            ..Default::default()
         }
     }
 }
@@ -158,8 +677,10 @@ impl From<&GitRepo> for GitRepoInfo {
             url: repo.url.clone(),
             head: repo.head.clone(),
             credentials: repo.credentials.clone(),
+            credential_provider: repo.credential_provider.clone(),
             branch: repo.branch.clone(),
             path: repo.path.clone(),
+            ..Default::default()
         }
     }
 }
@@ -170,8 +691,12 @@ impl From<&GitRepoInfo> for GitRepoCloneRequest {
             url: repo.url.clone(),
             head: repo.head.clone(),
             credentials: repo.credentials.clone(),
+            credential_provider: repo.credential_provider.clone(),
             branch: repo.branch.clone(),
             path: repo.path.clone(),
+            host_key_policy: repo.host_key_policy,
+            proxy: repo.proxy.clone(),
+            ..Default::default()
         }
     }
 }
@@ -183,8 +708,21 @@ impl From<&GitRepoCloneRequest> for GitRepoInfo {
             url: repo.url.clone(),
             head: repo.head.clone(),
             credentials: repo.credentials.clone(),
+            credential_provider: repo.credential_provider.clone(),
             branch: repo.branch.clone(),
             path: repo.path.clone(),
+            host_key_policy: repo.host_key_policy,
+            proxy: repo.proxy.clone(),
+            ..Default::default()
         }
     }
 }
+
+impl std::ops::Deref for GitRepoReadOnly {
+    type Target = GitRepoInfo;
+
+    /// Borrow the `GitRepoInfo`, for its read-only methods
+    fn deref(&self) -> &Self::Target {
+        &self.info
+    }
+}
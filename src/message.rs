@@ -0,0 +1,86 @@
+use crate::{MessageRules, MessageViolation};
+
+use color_eyre::eyre::{eyre, Result};
+
+/// Check a commit `message` against `rules`, before creating a commit.
+/// Returns every rule the message breaks, or an empty `Vec` if it passes.
+/// Returns `Err` if `rules` can't be met by any message, like a `max_subject_length` of 0,
+/// or an allowed type that isn't a valid conventional commit type.
+///
+/// Lines starting with `#` are ignored, like git does for messages written in an editor
+pub fn validate_message(message: &str, rules: &MessageRules) -> Result<Vec<MessageViolation>> {
+    if rules.max_subject_length == Some(0) {
+        return Err(eyre!("A max subject length of 0 rejects every message"));
+    }
+
+    if let Some(invalid) = rules
+        .allowed_types
+        .iter()
+        .find(|t| t.is_empty() || !t.chars().all(|c| c.is_ascii_alphanumeric()))
+    {
+        return Err(eyre!("Not a valid conventional commit type: {:?}", invalid));
+    }
+
+    let lines: Vec<&str> = message
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(str::trim_end)
+        .collect();
+
+    let mut violations = Vec::new();
+
+    let subject = lines.first().copied().unwrap_or_default();
+
+    if subject.trim().is_empty() {
+        violations.push(MessageViolation::EmptySubject);
+        return Ok(violations);
+    }
+
+    if let Some(max) = rules.max_subject_length {
+        let length = subject.chars().count();
+        if length > max {
+            violations.push(MessageViolation::SubjectTooLong { length, max });
+        }
+    }
+
+    if lines.get(1).is_some_and(|line| !line.is_empty()) {
+        violations.push(MessageViolation::MissingBlankLine);
+    }
+
+    if rules.require_body && lines.iter().skip(1).all(|line| line.is_empty()) {
+        violations.push(MessageViolation::MissingBody);
+    }
+
+    if !rules.allowed_types.is_empty() {
+        match commit_type(subject) {
+            Some(found) if !rules.allowed_types.iter().any(|t| t == found) => {
+                violations.push(MessageViolation::TypeNotAllowed {
+                    found: found.to_string(),
+                });
+            }
+            Some(_) => {}
+            None => violations.push(MessageViolation::MissingType),
+        }
+    }
+
+    Ok(violations)
+}
+
+/// Returns the conventional commit type of `subject`, like `feat` in `feat(cli)!: Add flag`
+fn commit_type(subject: &str) -> Option<&str> {
+    let (prefix, _description) = subject.split_once(": ")?;
+
+    let prefix = prefix.strip_suffix('!').unwrap_or(prefix);
+
+    let commit_type = match prefix.split_once('(') {
+        Some((commit_type, scope)) if scope.ends_with(')') => commit_type,
+        Some(_) => return None,
+        None => prefix,
+    };
+
+    if !commit_type.is_empty() && commit_type.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Some(commit_type)
+    } else {
+        None
+    }
+}
@@ -1,11 +1,16 @@
+use std::ffi::OsStr;
 use std::fmt::Debug;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use crate::{GitCommitMeta, GitCredentials, GitRepo, GitRepoCloneRequest, GitRepoInfo};
+use crate::{
+    CredentialProvider, GitCommitMeta, GitCredentials, GitRepo, GitRepoCloneRequest, GitRepoInfo,
+    GitRepoReadOnly, HeadState, OpenedRepo, SharedCredentialProvider, UrlKind,
+};
 use git_url_parse::GitUrl;
 
-use git2::{Branch, Commit, Repository};
+use git2::{Branch, Commit, ErrorCode, Repository, RepositoryOpenFlags};
 
 use color_eyre::eyre::{eyre, Result};
 use tracing::debug;
@@ -15,6 +20,8 @@ impl GitRepo {
     /// - If a local `branch` is not provided, current checked out branch will be used.
     ///   The provided branch will be resolved to its remote branch name
     /// - If `commit_id` is not provided, the current commit (the HEAD of `branch`) will be used
+    /// - `path` can be a linked worktree from `git worktree add`. Its own HEAD is used, while refs
+    ///   and remotes are shared with the main repo
     pub fn open(path: PathBuf, branch: Option<String>, commit_id: Option<String>) -> Result<Self> {
         // First we open the repository and get the remote_url and parse it into components
         let local_repo = Self::to_repository_from_path(path.clone())?;
@@ -24,8 +31,10 @@ impl GitRepo {
         let working_branch_name =
             if let Ok(Some(git2_branch)) = GitRepoInfo::get_git2_branch(&local_repo, &branch) {
                 git2_branch.name()?.map(str::to_string)
+            } else if branch.is_none() {
+                // Detached HEAD, or an unborn branch with no commits yet
+                Self::unborn_branch_name(&local_repo)
             } else {
-                // Detached HEAD
                 None
             };
 
@@ -54,7 +63,72 @@ impl GitRepo {
         }
     }
 
-    /// Set the location of `GitRepo` on the filesystem
+    /// Like `open()`, but also returns how `HEAD` was resolved and whether the current branch has a remote.
+    /// Use this to tell detached HEAD apart from a branch without a remote, which `open()` returns the same way
+    pub fn open_detailed(
+        path: PathBuf,
+        branch: Option<String>,
+        commit_id: Option<String>,
+    ) -> Result<OpenedRepo> {
+        let repo = Self::open(path.clone(), branch, commit_id)?;
+
+        let local_repo = Self::to_repository_from_path(path)?;
+        let has_remote = GitRepoInfo::git_remote_from_repo(&local_repo)?.is_some();
+
+        let head_state = match local_repo.head() {
+            Err(e) if e.code() == ErrorCode::UnbornBranch => HeadState::Unborn,
+            Err(e) => return Err(e.into()),
+            Ok(head) if local_repo.head_detached()? => {
+                HeadState::DetachedAt(GitCommitMeta::from(&head.peel_to_commit()?))
+            }
+            Ok(head) => {
+                HeadState::Branch(String::from_utf8_lossy(head.shorthand_bytes()).to_string())
+            }
+        };
+
+        Ok(OpenedRepo {
+            repo,
+            head_state,
+            has_remote,
+        })
+    }
+
+    /// Like `open()`, but returns a `GitRepoReadOnly`, which has no `into_mut()`, so nothing can modify the repo through it.
+    /// Use this when sharing a repo between concurrent readers
+    pub fn open_readonly(
+        path: PathBuf,
+        branch: Option<String>,
+        commit_id: Option<String>,
+    ) -> Result<GitRepoReadOnly> {
+        Ok(GitRepoReadOnly {
+            info: Self::open(path, branch, commit_id)?.to_info(),
+        })
+    }
+
+    /// Returns a `GitRepo` after parsing metadata from a git directory (e.g. `.git`, or the directory
+    /// given to `git --git-dir`), rather than from the root of the worktree.
+    /// The worktree is resolved by git2 from the gitdir's `core.worktree` config, if set.
+    /// Use `workdir()` to get the resolved worktree location.
+    pub fn open_gitdir(gitdir: PathBuf) -> Result<Self> {
+        // Don't search parent directories, or look for a `.git` subdirectory
+        let flags = RepositoryOpenFlags::NO_SEARCH | RepositoryOpenFlags::NO_DOTGIT;
+
+        if Repository::open_ext(&gitdir, flags, &[] as &[&OsStr]).is_err() {
+            return Err(eyre!("Not a git directory: {gitdir:#?}"));
+        }
+
+        Self::open(gitdir, None, None)
+    }
+
+    /// Returns the location of the worktree for the repo at `self.path`.
+    /// Returns `None` if the repo is bare
+    pub fn workdir(&self) -> Result<Option<PathBuf>> {
+        Ok(self.to_repository()?.workdir().map(Path::to_path_buf))
+    }
+
+    /// Set the location of `GitRepo` on the filesystem.
+    /// The path is canonicalized, so any symlinks in `path` are resolved.
+    /// Use `with_path_raw()` to keep `path` as given
     pub fn with_path(mut self, path: PathBuf) -> Result<Self> {
         // We want to get the absolute path of the directory of the repo
         self.path = if let Ok(p) = fs::canonicalize(path) {
@@ -65,6 +139,18 @@ impl GitRepo {
         Ok(self)
     }
 
+    /// Set the location of `GitRepo` on the filesystem, without canonicalizing.
+    /// Unlike `with_path()`, symlinks in `path` are not resolved. This is useful when
+    /// intentionally working through a symlinked directory, like some CI workspaces
+    pub fn with_path_raw(mut self, path: PathBuf) -> Result<Self> {
+        if !path.exists() {
+            return Err(eyre!("Directory was not found"));
+        }
+
+        self.path = Some(path);
+        Ok(self)
+    }
+
     /// Intended to be set with the remote name branch of GitRepo
     pub fn with_branch(mut self, branch: Option<String>) -> Self {
         if let Some(b) = branch {
@@ -95,7 +181,8 @@ impl GitRepo {
 
                 let commit = GitCommitMeta::new(c.id())
                     .with_message(Some(commit_msg))
-                    .with_timestamp(c.time().seconds());
+                    .with_timestamp(c.committer().when().seconds())
+                    .with_author_timestamp(c.author().when().seconds());
 
                 self.head = Some(commit);
                 self
@@ -114,6 +201,16 @@ impl GitRepo {
         self
     }
 
+    /// Set a custom `CredentialProvider`, used instead of `GitCredentials` for private repos.
+    /// Only supported by operations that use `git2`, not the `git` CLI
+    pub fn with_credential_provider<C: CredentialProvider + 'static>(
+        mut self,
+        provider: C,
+    ) -> Self {
+        self.credential_provider = Some(SharedCredentialProvider(Arc::new(provider)));
+        self
+    }
+
     /// Create a new `GitRepo` with `url`.
     /// Use along with `with_*` methods to set other fields of `GitRepo`.
     /// Use `GitRepoCloner` if you need to clone the repo, and convert back with `GitRepo.into()`
@@ -127,6 +224,7 @@ impl GitRepo {
         Ok(Self {
             url,
             credentials: None,
+            credential_provider: None,
             head: None,
             branch: None,
             path: None,
@@ -137,6 +235,12 @@ impl GitRepo {
         self.into()
     }
 
+    /// Returns whether `self.url` is a remote url, or a local path.
+    /// Repos opened from a branch without a remote have a `Local` url
+    pub fn url_kind(&self) -> UrlKind {
+        UrlKind::from(&self.url)
+    }
+
     pub fn to_info(&self) -> GitRepoInfo {
         self.into()
     }
@@ -159,6 +263,20 @@ impl GitRepo {
         }
     }
 
+    /// Returns the name of the branch `HEAD` points at, if that branch has no commits yet,
+    /// like in a freshly initialized repo
+    fn unborn_branch_name(r: &Repository) -> Option<String> {
+        match r.head() {
+            Err(e) if e.code() == ErrorCode::UnbornBranch => r
+                .find_reference("HEAD")
+                .ok()?
+                .symbolic_target()?
+                .strip_prefix("refs/heads/")
+                .map(str::to_string),
+            _ => None,
+        }
+    }
+
     /// Return a `git2::Commit` that refers to the commit object requested for building
     /// If commit id is not provided, then we'll use the HEAD commit of whatever branch is active or provided
     fn get_git2_commit<'repo>(
@@ -171,7 +289,14 @@ impl GitRepo {
             // Do I need to verify that we're in detached head?
             // if r.head_detached()? {}
 
-            if let Ok(commit) = r.head()?.peel_to_commit() {
+            let head = match r.head() {
+                Ok(head) => head,
+                // There's no commit yet on an unborn branch
+                Err(e) if e.code() == ErrorCode::UnbornBranch => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+
+            if let Ok(commit) = head.peel_to_commit() {
                 return Ok(Some(commit));
             } else {
                 return Err(eyre!(
@@ -251,9 +376,46 @@ impl GitRepo {
                         Ok(None)
                     }
                 } else {
-                    unreachable!("We should have returned Err() early if both commit and branch not provided. We need one.")
+                    // We should have returned early if both commit and branch not provided. We need one.
+                    Err(eyre!(
+                        "internal invariant violated: no branch or commit id to find a commit with"
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if `self` and `other` refer to the same commit on the same branch of the same remote,
+    /// regardless of where they are checked out on disk, or which credentials they use.
+    /// Urls are compared without any user or token, and `head` is compared by commit id
+    pub fn same_remote_ref(&self, other: &GitRepo) -> bool {
+        self.url.trim_auth() == other.url.trim_auth()
+            && self.branch == other.branch
+            && self.head.as_ref().map(|h| &h.id) == other.head.as_ref().map(|h| &h.id)
+    }
+
+    /// Returns a key for `self.url` that is the same for equivalent urls of a repo, like
+    /// `https://github.com/x/y`, `https://github.com/x/y.git` and `git@github.com:x/y.git`, which are `github.com/x/y`.
+    /// The scheme, credentials, default ports and `.git` suffix are dropped, and the host is lowercased.
+    /// The path keeps its case, since not every server treats it as case-insensitive
+    pub fn canonical_url(&self) -> String {
+        let path = self.url.path.trim_matches('/');
+        let path = path.strip_suffix(".git").unwrap_or(path);
+
+        match &self.url.host {
+            Some(host) => {
+                let host = host.to_lowercase();
+
+                match self.url.port {
+                    Some(port) if ![22, 80, 443, 9418].contains(&port) => {
+                        format!("{}:{}/{}", host, port, path)
+                    }
+                    _ => format!("{}/{}", host, path),
                 }
             }
+            // Local paths are kept absolute
+            None if self.url.path.starts_with('/') => format!("/{}", path),
+            None => path.to_string(),
         }
     }
 
@@ -0,0 +1,218 @@
+use crate::cli;
+use crate::info;
+use crate::{BranchRelationship, GitRepo, GitRepoInfo, GitRepoMut, TagMeta, UrlKind};
+
+use color_eyre::eyre::{eyre, Context, ContextCompat, Result};
+use git2::{BranchType, Oid};
+
+impl GitRepoMut {
+    /// Returns the read-only `GitRepoInfo` of the repo, for everything that doesn't modify it
+    pub fn to_info(&self) -> GitRepoInfo {
+        self.info.clone()
+    }
+
+    /// Attach `key=value` metadata to `commit`, replacing any existing value for `key`.
+    /// Metadata is stored as a git note of `key=value` lines, in the `refs/notes/git-meta` namespace,
+    /// so it doesn't clash with other notes. Keys can't contain `=`, and neither can contain newlines
+    pub fn set_commit_metadata<S: AsRef<str>>(
+        &self,
+        commit: S,
+        key: &str,
+        value: &str,
+    ) -> Result<()> {
+        if key.is_empty() || key.contains(['=', '\n']) || value.contains('\n') {
+            return Err(eyre!("Invalid commit metadata key or value: {:?}", key));
+        }
+
+        let repo = self.info.to_repo().to_repository()?;
+        let commit = GitRepoInfo::commit_from_repository(&repo, commit.as_ref())?;
+
+        let mut metadata = GitRepoInfo::commit_metadata_from_repository(&repo, commit.id())?;
+        match metadata.iter_mut().find(|(k, _)| k == key) {
+            Some(entry) => entry.1 = value.to_string(),
+            None => metadata.push((key.to_string(), value.to_string())),
+        }
+
+        let note: String = metadata
+            .iter()
+            .map(|(k, v)| format!("{}={}\n", k, v))
+            .collect();
+
+        let signature = repo.signature().wrap_err(
+            "Unable to write commit metadata without a configured user.name and user.email",
+        )?;
+
+        repo.note(
+            &signature,
+            &signature,
+            Some(info::COMMIT_METADATA_NOTES_REF),
+            commit.id(),
+            &note,
+            true,
+        )?;
+
+        Ok(())
+    }
+
+    /// Fetch all tags from the remote into the repo, like `git fetch --tags`, without
+    /// updating any branches. Returns all of the local tags afterwards, the same as `list_tags(None)`.
+    /// Unlike `remote_refs()`, the tag objects are downloaded, so annotated tags include their tagger and message
+    pub fn fetch_tags(&self) -> Result<Vec<TagMeta>> {
+        let repo = self.info.to_repo().to_repository()?;
+
+        let remote_name = self.info.get_remote_name(&repo)?;
+        let mut remote = repo.find_remote(&remote_name)?;
+
+        let mut fetch_options = git2::FetchOptions::new();
+        fetch_options.remote_callbacks(self.info.build_git2_remotecallback()?);
+        if let Some(proxy) = self.info.proxy.to_git2() {
+            fetch_options.proxy_options(proxy);
+        }
+        // The refspec already covers tags, so don't let the remote's tag settings follow any others
+        fetch_options.download_tags(git2::AutotagOption::None);
+
+        remote
+            .fetch(&["refs/tags/*:refs/tags/*"], Some(&mut fetch_options), None)
+            .wrap_err(format!("Could not fetch tags from {}", remote_name))?;
+
+        self.info.list_tags(None)
+    }
+
+    /// Fetch `additional` commits of history from the remote into a shallow clone,
+    /// with `git fetch --deepen`, using the repo's credentials. Requires the `git` CLI.
+    /// Returns the `GitRepo` re-opened at its current checkout
+    pub fn deepen(&self, additional: u32) -> Result<GitRepo> {
+        UrlKind::require_remote(&self.info.url, "Fetching")?;
+
+        let path = self.info.path.clone().wrap_err("No path to GitRepo set")?;
+        let repo = GitRepo::to_repository_from_path(&path)?;
+
+        let remote_name = self
+            .info
+            .get_remote_name(&repo)
+            .wrap_err("Could not read remote name from git2::Repository")?;
+        let remote = repo.find_remote(&remote_name)?;
+
+        let (fetch_url, fetch_config) = cli::authenticated_url(
+            &self.info.url,
+            &self.info.credentials,
+            &self.info.credential_provider,
+            self.info.host_key_policy,
+            &self.info.proxy,
+        )?;
+
+        let mut fetch_command = cli::git_command(Some(&path));
+        for config in fetch_config {
+            fetch_command.arg("-c").arg(config);
+        }
+
+        // Fetch from the url, so credentials can be passed, but update the remote's refs
+        fetch_command
+            .arg("fetch")
+            .arg(format!("--deepen={}", additional))
+            .arg(format!("{}", fetch_url));
+
+        for refspec in remote.fetch_refspecs()?.iter().flatten() {
+            fetch_command.arg(refspec);
+        }
+
+        cli::run(&mut fetch_command).wrap_err("Failed to deepen shallow clone")?;
+
+        Ok(GitRepo::open(path, None, None)?.with_credentials(self.info.credentials.clone()))
+    }
+
+    /// Write a commit-graph for all commits reachable from any ref, with
+    /// `git commit-graph write --reachable`.
+    ///
+    /// *Note:* This requires `git` CLI to be installed
+    pub fn write_commit_graph(&self) -> Result<()> {
+        let path = self.info.path.as_ref().wrap_err("No path set to open")?;
+
+        cli::run_git(Some(path), &["commit-graph", "write", "--reachable"])
+            .wrap_err("Could not write commit-graph")?;
+
+        Ok(())
+    }
+
+    /// Clean up and compress the repo, with `git gc`, or `git gc --aggressive` if `aggressive`.
+    /// Keeps long-lived mirrors from bloating with loose objects.
+    ///
+    /// *Note:* This requires `git` CLI to be installed
+    pub fn gc(&self, aggressive: bool) -> Result<()> {
+        let path = self.info.path.as_ref().wrap_err("No path set to open")?;
+
+        let mut args = vec!["gc", "--quiet"];
+        if aggressive {
+            args.push("--aggressive");
+        }
+
+        cli::run_git(Some(path), &args).wrap_err("Could not gc repo")?;
+
+        Ok(())
+    }
+
+    /// Repack all objects of the repo into a single pack, with `git repack -ad`.
+    /// Objects that were already packed but are unreachable are dropped.
+    ///
+    /// *Note:* This requires `git` CLI to be installed
+    pub fn repack(&self) -> Result<()> {
+        let path = self.info.path.as_ref().wrap_err("No path set to open")?;
+
+        cli::run_git(Some(path), &["repack", "-a", "-d", "--quiet"])
+            .wrap_err("Could not repack repo")?;
+
+        Ok(())
+    }
+
+    /// Fetch the remote HEAD of the repo's `branch`, and compare it to the HEAD of the local branch
+    /// (or the repo's `head`, if there is no local branch with that name).
+    /// The remote HEAD is fetched into a temporary `refs/git-meta/` ref, which is deleted afterwards,
+    /// but the fetched objects are kept.
+    /// Unlike `new_commits_exist()`, this doesn't assume linear history, so a force-pushed or
+    /// rebased remote branch is reported as `BranchRelationship::Diverged`
+    pub fn branch_relationship(&self) -> Result<BranchRelationship> {
+        let repo = self.info.to_repo().to_repository()?;
+
+        let branch = self.info.branch.as_ref().wrap_err("No branch set")?;
+
+        // `self.info.head` may already point at the remote branch HEAD, so prefer the local branch
+        let local_id = if let Ok(local_branch) = repo.find_branch(branch, BranchType::Local) {
+            local_branch.get().peel_to_commit()?.id()
+        } else {
+            let head = self.info.head.as_ref().wrap_err("No head commit set")?;
+            Oid::from_str(&head.id)?
+        };
+
+        let remote_name = self.info.get_remote_name(&repo)?;
+        let mut remote = repo.find_remote(&remote_name)?;
+
+        // Write to a ref we own, so we don't move the user's remote-tracking branch
+        let remote_ref = format!("refs/git-meta/{}/{}", remote_name, branch);
+        let refspec = format!("+refs/heads/{}:{}", branch, remote_ref);
+
+        let mut fetch_options = git2::FetchOptions::new();
+        fetch_options.remote_callbacks(self.info.build_git2_remotecallback()?);
+        if let Some(proxy) = self.info.proxy.to_git2() {
+            fetch_options.proxy_options(proxy);
+        }
+
+        remote
+            .fetch(&[refspec.as_str()], Some(&mut fetch_options), None)
+            .wrap_err(format!("Could not fetch {} from {}", branch, remote_name))?;
+
+        let mut fetched = repo.find_reference(&remote_ref)?;
+        let remote_id = fetched.peel_to_commit()?.id();
+        fetched.delete()?;
+
+        let (ahead, behind) = repo
+            .graph_ahead_behind(local_id, remote_id)
+            .wrap_err("Could not compare local and remote HEADs")?;
+
+        Ok(match (ahead, behind) {
+            (0, 0) => BranchRelationship::UpToDate,
+            (0, behind) => BranchRelationship::Behind(behind),
+            (ahead, 0) => BranchRelationship::Ahead(ahead),
+            (ahead, behind) => BranchRelationship::Diverged { ahead, behind },
+        })
+    }
+}
@@ -1,7 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 use chrono::prelude::*;
+use color_eyre::eyre::Result;
 use git_url_parse::GitUrl;
 
 /// `GitCredentials` holds authentication information for a remote git repository
@@ -19,6 +23,98 @@ pub enum GitCredentials {
     },
 }
 
+/// `CredentialProvider` supplies authentication for remote operations that use `git2`,
+/// for auth that `GitCredentials` can't express, like short-lived tokens fetched per-request.
+/// `GitCredentials` implements this, so either can be used
+pub trait CredentialProvider: Send + Sync {
+    /// Returns the callbacks used to authenticate when connecting to a remote
+    fn remote_callbacks(&self) -> Result<git2::RemoteCallbacks<'_>>;
+}
+
+/// A shared `CredentialProvider`, so repos holding one can still be cloned and compared.
+/// Two `SharedCredentialProvider` are equal if they share the same provider.
+/// Set with `with_credential_provider()`
+#[derive(Clone)]
+pub struct SharedCredentialProvider(pub(crate) Arc<dyn CredentialProvider>);
+
+impl fmt::Debug for SharedCredentialProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedCredentialProvider(..)")
+    }
+}
+
+impl PartialEq for SharedCredentialProvider {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// `CredentialConfig` is the credential strategy of a repo, resolved once with `GitRepoInfo::credential_config()`.
+/// `git2::RemoteCallbacks` can only be used for one operation, so use `callbacks()` to build fresh ones
+/// from the same config for each step of a multi-step network flow, like connecting and then fetching
+#[derive(Clone, Debug, PartialEq)]
+pub struct CredentialConfig {
+    /// The remote url, used to decide whether to verify ssh host keys
+    pub url: GitUrl,
+    /// The ssh key or user/pass to authenticate with. Always `None` if `credential_provider` is set
+    pub credentials: Option<GitCredentials>,
+    /// A custom provider of credentials, used instead of `credentials`
+    pub credential_provider: Option<SharedCredentialProvider>,
+    /// How to verify the host key of ssh remotes
+    pub host_key_policy: HostKeyPolicy,
+}
+
+/// `GitMetaError` is returned (wrapped in a `color_eyre::Report`) for failures that callers may
+/// want to handle specifically. Check for one with `report.downcast_ref::<GitMetaError>()`
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum GitMetaError {
+    /// Connecting to the remote took longer than the configured timeout
+    ConnectTimeout { url: String, timeout: Duration },
+    /// The remote has more objects to transfer than the configured limit, so the clone was aborted
+    ObjectLimitExceeded {
+        max_objects: usize,
+        total_objects: usize,
+    },
+}
+
+/// `UrlKind` classifies whether a repo url refers to a remote server, or to a path on the local filesystem
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UrlKind {
+    /// A url for a remote server, like `https://`, `ssh://` or scp-like `git@host:owner/repo`
+    Remote,
+    /// A local filesystem path, or a `file://` url
+    Local,
+}
+
+/// `HostKeyPolicy` is how the host key of an ssh remote is verified against `known_hosts`,
+/// like ssh's `StrictHostKeyChecking` option
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HostKeyPolicy {
+    /// Only connect to hosts with a matching key in `known_hosts`
+    #[default]
+    Strict,
+    /// Also connect to hosts that aren't in `known_hosts` yet, and record their key. Hosts with a mismatched key are refused.
+    /// Only supported by operations that use the `git` CLI. `git2` operations on ssh urls return `Err`,
+    /// since they can't record the new key
+    AcceptNew,
+    /// Connect without verifying the host key
+    AcceptAll,
+}
+
+/// `ProxyConfig` sets the proxy used for network operations.
+/// `git2` only connects through a proxy for `https` urls
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ProxyConfig {
+    /// Don't configure a proxy. `git2` connects directly, and the `git` CLI uses its own config
+    #[default]
+    None,
+    /// Detect the proxy from git config (`http.proxy`) and the environment, like the `git` CLI does
+    Auto,
+    /// Connect through the proxy at this url
+    Url(String),
+}
+
 /// Use `GitRepo::open()` to read a repo on disk. `GitRepo::new()` if you need to clone the repo.
 ///
 /// Use `GitRepoCloneRequest` to clone repo to disk
@@ -31,6 +127,9 @@ pub struct GitRepo {
     pub head: Option<GitCommitMeta>,
     /// The ssh key or user/pass needed to clone for private repo
     pub credentials: Option<GitCredentials>,
+    /// A custom provider of credentials, used instead of `credentials` when set.
+    /// This can be configured with `with_credential_provider()`
+    pub credential_provider: Option<SharedCredentialProvider>,
     /// The name of the remote branch.
     /// This can be configured with a local branch name prior to clone with `with_branch()`.
     pub branch: Option<String>,
@@ -38,6 +137,28 @@ pub struct GitRepo {
     pub path: Option<PathBuf>,
 }
 
+/// `HeadState` is what `HEAD` points at in a repo on disk
+#[derive(Clone, Debug, PartialEq)]
+pub enum HeadState {
+    /// `HEAD` is on the local branch with this name
+    Branch(String),
+    /// `HEAD` is detached at this commit
+    DetachedAt(GitCommitMeta),
+    /// `HEAD` is on a branch with no commits yet, like in a freshly initialized repo
+    Unborn,
+}
+
+/// `OpenedRepo` is a `GitRepo` along with how it was resolved. Returned by `GitRepo::open_detailed()`
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpenedRepo {
+    /// The same `GitRepo` returned by `GitRepo::open()`
+    pub repo: GitRepo,
+    /// What `HEAD` points at, since `repo.branch` is `None` for both detached HEAD and branches without a remote
+    pub head_state: HeadState,
+    /// Whether the current branch has an upstream remote. If not, `repo.url` is the local path of the repo
+    pub has_remote: bool,
+}
+
 /// Represents request to clone repo to disk
 ///
 /// After cloning, returns a `GitRepo`
@@ -51,11 +172,39 @@ pub struct GitRepoCloneRequest {
     pub head: Option<GitCommitMeta>,
     /// The ssh key or user/pass needed to clone for private repo
     pub credentials: Option<GitCredentials>,
+    /// A custom provider of credentials, used instead of `credentials` when set.
+    /// This can be configured with `with_credential_provider()`
+    pub credential_provider: Option<SharedCredentialProvider>,
     /// The name of the remote branch.
     /// This can be configured with a local branch name prior to clone with `with_branch()`.
     pub branch: Option<String>,
+    /// Check out this tag in detached HEAD after cloning, instead of a branch.
+    /// This can be configured with `with_tag()`
+    pub tag: Option<String>,
     /// The location of the repo on disk
     pub path: Option<PathBuf>,
+    /// Only check out these directories, using cone-mode sparse checkout.
+    /// This can be configured with `with_sparse_paths()`
+    pub sparse_paths: Option<Vec<String>>,
+    /// A local repo to reuse objects from while cloning, like `git clone --reference`.
+    /// This can be configured with `with_reference_repo()`
+    pub reference_repo: Option<PathBuf>,
+    /// Keep borrowing objects from `reference_repo` after cloning, instead of copying them
+    /// with `--dissociate`. This can be configured with `with_keep_reference()`
+    pub keep_reference: bool,
+    /// Abort the clone with `GitMetaError::ObjectLimitExceeded` if the remote has more objects
+    /// to transfer than this. `None` is unlimited. This can be configured with `with_max_objects()`
+    pub max_objects: Option<usize>,
+    /// How to verify the host key of ssh remotes. This can be configured with `with_host_key_check()`
+    pub host_key_policy: HostKeyPolicy,
+    /// Populate the gitdir without checking out any files, like `git clone --no-checkout`.
+    /// This can be configured with `with_no_checkout()`
+    pub no_checkout: bool,
+    /// The proxy to connect through. This can be configured with `with_proxy()`
+    pub proxy: ProxyConfig,
+    /// Clone history back to this date, instead of a depth of 1, like `git clone --shallow-since`.
+    /// This can be configured with `with_shallow_since()`
+    pub shallow_since: Option<DateTime<Utc>>,
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
@@ -66,11 +215,46 @@ pub struct GitRepoInfo {
     pub head: Option<GitCommitMeta>,
     /// The ssh key or user/pass needed to clone for private repo
     pub credentials: Option<GitCredentials>,
+    /// A custom provider of credentials, used instead of `credentials` when set.
+    /// This can be configured with `with_credential_provider()`
+    pub credential_provider: Option<SharedCredentialProvider>,
     /// The name of the remote branch.
     /// This can be configured with a local branch name prior to clone with `with_branch()`.
     pub branch: Option<String>,
     /// The location of the repo on disk
     pub path: Option<PathBuf>,
+    /// The parent directory for temporary scratch clones. This can be configured with `with_temp_dir()`
+    pub temp_dir: Option<PathBuf>,
+    /// How long to wait when listing remote refs before giving up with `GitMetaError::ConnectTimeout`.
+    /// `None` waits indefinitely. This can be configured with `with_connect_timeout()`
+    pub connect_timeout: Option<Duration>,
+    /// How to verify the host key of ssh remotes. This can be configured with `with_host_key_check()`
+    pub host_key_policy: HostKeyPolicy,
+    /// The proxy to connect through. This can be configured with `with_proxy()`
+    pub proxy: ProxyConfig,
+}
+
+/// `GitRepoReadOnly` is a `GitRepoInfo` returned by `GitRepo::open_readonly()`, for sharing a repo between
+/// concurrent readers. The read-only methods of `GitRepoInfo` are available through `Deref`,
+/// but it can't be turned into a `GitRepoMut`
+///
+/// ```compile_fail
+/// # use git_meta::GitRepo;
+/// let repo = GitRepo::open_readonly(".".into(), None, None).unwrap();
+/// repo.into_mut();
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct GitRepoReadOnly {
+    pub(crate) info: GitRepoInfo,
+}
+
+/// `GitRepoMut` is for maintenance operations that modify the repo on disk: writing notes, fetching tags,
+/// deepening shallow clones, `gc`, `repack` and writing the commit-graph.
+/// It doesn't cover commits, resets or checkouts.
+/// `GitRepoInfo` only reads, so these writes have to go through `GitRepoInfo::into_mut()`
+#[derive(Clone, Debug, PartialEq)]
+pub struct GitRepoMut {
+    pub(crate) info: GitRepoInfo,
 }
 
 /// `GitCommitMeta` holds basic info about a single commit
@@ -80,8 +264,354 @@ pub struct GitCommitMeta {
     pub id: String,
     /// The commit message of the commit
     pub message: Option<String>,
-    /// The timestamp of the commit in `Utc`
+    /// The timestamp of the commit in `Utc`. This is the committer time, the same as `committer_timestamp`
+    pub timestamp: Option<DateTime<Utc>>,
+    /// When the commit was originally authored, in `Utc`. Rebases and amends keep this time
+    pub author_timestamp: Option<DateTime<Utc>>,
+    /// When the commit was last committed, in `Utc`. Rebases and amends update this time
+    pub committer_timestamp: Option<DateTime<Utc>>,
+    /// Whether the commit carries a signature, like a GPG signature. This only checks that one is
+    /// present, not that it's valid. `None` if it wasn't checked, such as by `commit_log()` without `with_signatures`
+    pub signed: Option<bool>,
+}
+
+/// `GitDiffOptions` configures how diffs between commits are computed.
+/// Unset options use the same defaults as plain `git diff`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GitDiffOptions {
+    /// The number of unchanged lines around each hunk, like `git diff -U<n>`. Defaults to 3
+    pub context_lines: Option<u32>,
+    /// The max number of unchanged lines between hunks before they are merged into one,
+    /// like `git diff --inter-hunk-context=<n>`. Defaults to 0
+    pub interhunk_lines: Option<u32>,
+    /// Only include changes to paths matching these pathspecs, like `git diff -- <path>`.
+    /// Empty includes all paths
+    pub pathspec: Vec<String>,
+    /// Leave out changes to paths matching these pathspecs, after `pathspec` is applied.
+    /// Like a negative pathspec `:!vendor`, and may be given with the `:!` or `:(exclude)` prefix
+    pub exclude: Vec<String>,
+    /// Ignore changes in whitespace, like `git diff -w`
+    pub ignore_whitespace: bool,
+}
+
+/// `Delta` is how a file changed in a diff
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Delta {
+    Unmodified,
+    Added,
+    Deleted,
+    Modified,
+    Renamed,
+    Copied,
+    Ignored,
+    Untracked,
+    /// The file changed between a regular file, symlink or submodule
+    Typechange,
+    Unreadable,
+    Conflicted,
+}
+
+/// `CommitChangeSummary` is a commit along with the files it changed.
+/// Returned by `GitRepoInfo::commit_change_summary()`
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommitChangeSummary {
+    /// The commit being summarized
+    pub commit: GitCommitMeta,
+    /// The files changed by the commit, and how
+    pub files: Vec<(PathBuf, Delta)>,
+}
+
+/// `DiffStats` summarizes a diff, like `git diff --shortstat`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DiffStats {
+    /// The number of files changed
+    pub files_changed: usize,
+    /// The number of lines added
+    pub insertions: usize,
+    /// The number of lines removed
+    pub deletions: usize,
+    /// The number of hunks. This depends on `context_lines` and `interhunk_lines` in `GitDiffOptions`
+    pub hunks: usize,
+}
+
+/// `CommitCount` is the result of counting commits, like `git rev-list --count`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CommitCount {
+    /// The number of commits counted
+    pub count: usize,
+    /// `false` if history was truncated by a shallow clone, so `count` is a lower bound
+    pub complete: bool,
+}
+
+/// `BlobInfo` identifies the contents of a file at a commit, without checking it out
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlobInfo {
+    /// The SHA-1 hash of the blob. Files with identical contents have the same `oid`
+    pub oid: String,
+    /// The size of the contents, in bytes
+    pub size: u64,
+    /// Whether git considers the contents binary, rather than text
+    pub is_binary: bool,
+}
+
+/// `GitSignature` holds the identity and time of an author, committer or tagger
+#[derive(Clone, Debug, PartialEq)]
+pub struct GitSignature {
+    /// The name of the signer. `None` if not valid utf-8
+    pub name: Option<String>,
+    /// The email of the signer. `None` if not valid utf-8
+    pub email: Option<String>,
+    /// The time of the signature in `Utc`
     pub timestamp: Option<DateTime<Utc>>,
 }
 
+/// `TagMeta` holds basic info about a single tag
+#[derive(Clone, Debug, PartialEq)]
+pub struct TagMeta {
+    /// The short name of the tag, without `refs/tags/`
+    pub name: String,
+    /// The commit the tag points at. Annotated tags are followed to their commit
+    pub target: GitCommitMeta,
+    /// Whether the tag is an annotated tag object, rather than a lightweight tag
+    pub annotated: bool,
+    /// The tagger of an annotated tag. Always `None` for lightweight tags
+    pub tagger: Option<GitSignature>,
+    /// The message of an annotated tag. Always `None` for lightweight tags
+    pub message: Option<String>,
+}
+
+/// The namespace a git reference belongs to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RefKind {
+    /// A local branch, under `refs/heads/`
+    Branch,
+    /// A remote-tracking branch, under `refs/remotes/`
+    RemoteBranch,
+    /// A tag, under `refs/tags/`
+    Tag,
+    /// Notes, under `refs/notes/`
+    Note,
+    /// Any other ref, like `HEAD` or `refs/stash`
+    Other,
+}
+
+/// `RefMeta` holds basic info about a single git reference
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RefMeta {
+    /// The full name of the ref, like `refs/heads/main`
+    pub name: String,
+    /// The namespace of the ref
+    pub kind: RefKind,
+    /// The name of the ref pointed to for symbolic refs, otherwise the object id
+    pub target: String,
+    /// Whether the ref is symbolic, like `HEAD`
+    pub is_symbolic: bool,
+}
+
+/// `RemoteRef` is a single ref advertised by a remote, like `git ls-remote` output
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteRef {
+    /// The full name of the ref, like `refs/heads/main` or `HEAD`
+    pub name: String,
+    /// The object id the ref points to
+    pub oid: String,
+    /// The name of the ref pointed to, if the remote advertised this as a symbolic ref (like `HEAD`)
+    pub symref_target: Option<String>,
+}
+
+/// `PrRefKind` is the hosting convention that a `PullRequestRef` was advertised with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrRefKind {
+    /// A GitHub pull request head, under `refs/pull/<number>/head`
+    GitHub,
+    /// A GitLab merge request head, under `refs/merge-requests/<number>/head`
+    GitLab,
+}
+
+/// `PullRequestRef` is the head of a pull request (or merge request) advertised by a remote
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PullRequestRef {
+    /// The number of the pull request
+    pub number: u64,
+    /// Whether this was a GitHub or GitLab ref
+    pub kind: PrRefKind,
+    /// The commit id at the head of the pull request
+    pub oid: String,
+}
+
 pub type BranchHeads = HashMap<String, GitCommitMeta>;
+
+/// `BranchHeadsDiff` holds the differences between two `BranchHeads`.
+/// Create with `BranchHeadsDiff::between()`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BranchHeadsDiff {
+    /// Branches on both sides whose HEAD commits differ, as `(ours, theirs)`
+    pub changed: HashMap<String, (GitCommitMeta, GitCommitMeta)>,
+    /// Branches that are only in ours
+    pub only_in_ours: BranchHeads,
+    /// Branches that are only in theirs
+    pub only_in_theirs: BranchHeads,
+}
+
+/// `RangeStats` summarizes the commits in a range, like `git log --shortstat from..to`.
+/// Returned by `GitRepoInfo::range_stats()`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RangeStats {
+    /// The number of commits in the range, including merges
+    pub commits: usize,
+    /// The number of lines added, summed across commits
+    pub insertions: usize,
+    /// The number of lines removed, summed across commits
+    pub deletions: usize,
+    /// Every path changed by at least one commit
+    pub files_touched: BTreeSet<PathBuf>,
+}
+
+/// `RepoSize` is the on-disk size of a repo's object database.
+/// Returned by `GitRepoInfo::repo_size()`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RepoSize {
+    /// The number of objects, both loose and in packs
+    pub object_count: usize,
+    /// The size of the files under `objects/pack`, in bytes
+    pub packed_size_bytes: u64,
+    /// The size of the loose object files, in bytes
+    pub loose_size_bytes: u64,
+}
+
+/// `MergeAnalysis` predicts the result of merging a commit into `HEAD`.
+/// Returned by `GitRepoInfo::merge_analysis()`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergeAnalysis {
+    /// Whether `HEAD` can be fast-forwarded to the commit
+    pub fast_forward: bool,
+    /// The paths that would conflict. Empty if the merge would be clean
+    pub conflicts: Vec<PathBuf>,
+}
+
+/// `RepoState` is the operation in progress in a repo's working copy, if any.
+/// Returned by `GitRepoInfo::repo_state()`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepoState {
+    /// No operation in progress
+    Clean,
+    Merge,
+    Revert,
+    /// A revert of multiple commits
+    RevertSequence,
+    CherryPick,
+    /// A cherry-pick of multiple commits
+    CherryPickSequence,
+    Bisect,
+    Rebase,
+    RebaseInteractive,
+    RebaseMerge,
+    /// Applying patches with `git am`
+    ApplyMailbox,
+    /// Either `git am` or `git rebase`, which can't be told apart
+    ApplyMailboxOrRebase,
+}
+
+/// `SubmoduleStatus` is where a submodule is found, and what has changed in it.
+/// Returned by `GitRepoInfo::submodule_status()`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SubmoduleStatus {
+    /// The superproject's `HEAD` commit contains the submodule
+    pub in_head: bool,
+    /// The superproject's index contains the submodule
+    pub in_index: bool,
+    /// The superproject's `.git/config` contains the submodule, i.e. it has been initialized
+    pub in_config: bool,
+    /// The superproject's working directory contains the submodule
+    pub in_workdir: bool,
+    /// The submodule is in the index, but not in `HEAD`
+    pub index_added: bool,
+    /// The submodule is in `HEAD`, but not in the index
+    pub index_deleted: bool,
+    /// The submodule commit in the index differs from `HEAD`
+    pub index_modified: bool,
+    /// The submodule directory exists, but isn't a checked out repo
+    pub wd_uninitialized: bool,
+    /// The submodule is in the working directory, but not in the index
+    pub wd_added: bool,
+    /// The submodule is in the index, but not in the working directory
+    pub wd_deleted: bool,
+    /// The submodule's checked out commit differs from the index
+    pub wd_modified: bool,
+    /// The submodule's own index has changes
+    pub wd_index_modified: bool,
+    /// The submodule's own working directory has changes to tracked files
+    pub wd_wd_modified: bool,
+    /// The submodule's own working directory has untracked files
+    pub wd_untracked: bool,
+}
+
+/// `BranchRelationship` describes how a local branch HEAD relates to its remote branch HEAD.
+/// Counts are the number of commits. Returned by `GitRepoInfo::branch_relationship()`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BranchRelationship {
+    /// The local and remote HEADs are the same commit
+    UpToDate,
+    /// The remote has commits the local branch does not
+    Behind(usize),
+    /// The local branch has commits the remote does not
+    Ahead(usize),
+    /// Both sides have commits the other does not, like after a force-push or rebase
+    Diverged { ahead: usize, behind: usize },
+}
+
+/// The kind of object a `TreeEntry` refers to, based on the entry's filemode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreeEntryKind {
+    /// A regular, non-executable blob (`0o100644`)
+    File,
+    /// An executable blob (`0o100755`)
+    Executable,
+    /// A symbolic link (`0o120000`). The blob content is the link target
+    Symlink,
+    /// A subtree (`0o040000`)
+    Directory,
+    /// A gitlink to a submodule commit (`0o160000`)
+    Submodule,
+}
+
+/// `TreeEntry` holds basic info about a single entry in a commit's tree
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeEntry {
+    /// The path of the entry, relative to the repo root
+    pub path: PathBuf,
+    /// The kind of object this entry refers to
+    pub kind: TreeEntryKind,
+    /// The SHA-1 hash of the object this entry refers to
+    pub id: String,
+}
+
+/// `MessageRules` are the checks `validate_message()` makes against a commit message.
+/// The default rules only require a non-empty subject
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MessageRules {
+    /// The max number of characters in the subject line. `None` is unlimited
+    pub max_subject_length: Option<usize>,
+    /// Require a body after the subject, separated by a blank line
+    pub require_body: bool,
+    /// Require a conventional commit type in the subject (e.g. `feat` in `feat(cli): Add flag`)
+    /// from this list. Empty allows any subject
+    pub allowed_types: Vec<String>,
+}
+
+/// A single rule a commit message breaks. Returned by `validate_message()`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MessageViolation {
+    /// The subject line is empty
+    EmptySubject,
+    /// The subject line has more characters than `MessageRules.max_subject_length`
+    SubjectTooLong { length: usize, max: usize },
+    /// The line after the subject isn't blank
+    MissingBlankLine,
+    /// There is no body, but `MessageRules.require_body` is set
+    MissingBody,
+    /// The subject doesn't start with a conventional commit type, like `fix: ...`
+    MissingType,
+    /// The conventional commit type isn't in `MessageRules.allowed_types`
+    TypeNotAllowed { found: String },
+}
@@ -0,0 +1,50 @@
+use git_meta::GitRepo;
+
+#[path = "common/mod.rs"]
+mod common;
+
+#[test]
+fn write_commit_graph() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "a.txt", "a", "First");
+    common::commit_file(&dir, "b.txt", "b", "Second");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert!(!info.has_commit_graph().unwrap());
+
+    info.clone().into_mut().write_commit_graph().unwrap();
+
+    assert!(info.has_commit_graph().unwrap());
+}
+
+#[test]
+fn commit_graph_in_linked_worktree() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "a.txt", "a", "First");
+
+    let worktree = mktemp::Temp::new_path();
+    common::git(
+        &dir,
+        &[
+            "worktree",
+            "add",
+            "--quiet",
+            "-b",
+            "other",
+            worktree.to_str().unwrap(),
+        ],
+    );
+
+    let info = GitRepo::open(worktree.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert!(!info.has_commit_graph().unwrap());
+
+    common::git(&dir, &["commit-graph", "write", "--reachable"]);
+
+    assert!(info.has_commit_graph().unwrap());
+}
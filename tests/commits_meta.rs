@@ -0,0 +1,292 @@
+use git_meta::{GitCommitMeta, GitRepo, GitRepoInfo};
+
+#[path = "common/mod.rs"]
+mod common;
+
+#[test]
+fn commits_meta_in_order() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "a.txt", "a", "First");
+    let second = common::commit_file(&dir, "b.txt", "b", "Second");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let metas = info
+        .commits_meta(&[second.clone(), first[..7].to_string()])
+        .unwrap();
+
+    assert_eq!(metas[0].id, second);
+    assert_eq!(metas[0].message, Some("Second\n".to_string()));
+    assert_eq!(metas[1].id, first);
+}
+
+#[test]
+fn commits_meta_missing_commit() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "a.txt", "a", "First");
+    let missing = "0000000000000000000000000000000000000000".to_string();
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert!(info
+        .commits_meta(&[first.clone(), missing.clone()])
+        .is_err());
+
+    let metas = info
+        .commits_meta_optional(&[first.clone(), missing])
+        .unwrap();
+    assert_eq!(metas[0].as_ref().unwrap().id, first);
+    assert!(metas[1].is_none());
+
+    // Only missing commits are `None`
+    assert_eq!(
+        info.commits_meta_optional(&["not-a-commit".to_string()])
+            .unwrap(),
+        vec![None]
+    );
+    let err = info
+        .commits_meta(&["z".repeat(40)])
+        .unwrap_err()
+        .to_string();
+    assert!(!err.contains("Commit not found"));
+    assert!(info.commits_meta_optional(&["z".repeat(40)]).is_err());
+}
+
+#[test]
+fn find_commit_from_meta() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "a.txt", "a", "First");
+
+    let repo = GitRepo::open(dir.to_path_buf(), None, None).unwrap();
+    let git2_repo = repo.to_repository().unwrap();
+
+    let head = repo.head.clone().unwrap();
+    let commit = GitRepoInfo::find_commit(&git2_repo, &head).unwrap();
+    assert_eq!(commit.id().to_string(), head.id);
+    assert_eq!(commit.summary(), Some("First"));
+
+    let missing = GitCommitMeta::new([0xab; 20]);
+    let err = GitRepoInfo::find_commit(&git2_repo, &missing).unwrap_err();
+    assert!(err.to_string().contains("not found"));
+}
+
+#[test]
+fn commit_metadata_round_trip() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "a.txt", "a", "First");
+    let second = common::commit_file(&dir, "b.txt", "b", "Second");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let repo_mut = info.clone().into_mut();
+
+    assert_eq!(info.get_commit_metadata(&first, "build").unwrap(), None);
+
+    repo_mut
+        .set_commit_metadata(&first, "build", "1234")
+        .unwrap();
+    repo_mut
+        .set_commit_metadata(&first, "status", "passed")
+        .unwrap();
+    repo_mut
+        .set_commit_metadata(&first, "build", "1235")
+        .unwrap();
+
+    assert_eq!(
+        info.get_commit_metadata(&first, "build").unwrap(),
+        Some("1235".to_string())
+    );
+    assert_eq!(
+        info.get_commit_metadata(&first, "status").unwrap(),
+        Some("passed".to_string())
+    );
+    assert_eq!(info.get_commit_metadata(&second, "build").unwrap(), None);
+
+    // Stored in our own notes namespace, as a key=value block
+    assert_eq!(
+        common::git(&dir, &["notes", "--ref=git-meta", "show", &first]),
+        "build=1235\nstatus=passed"
+    );
+    assert!(repo_mut.set_commit_metadata(&first, "a=b", "c").is_err());
+}
+
+#[test]
+fn head_timestamp_matches_commit_time() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "a.txt", "a", "First");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let committed: i64 = common::git(&dir, &["log", "-1", "--format=%ct"])
+        .parse()
+        .unwrap();
+    assert_eq!(info.head_timestamp().unwrap().timestamp(), committed);
+
+    // Detached HEAD works the same
+    common::git(&dir, &["checkout", "--quiet", "--detach", &first]);
+    assert_eq!(info.head_timestamp().unwrap().timestamp(), committed);
+}
+
+#[test]
+fn author_and_committer_timestamps_differ_after_amend() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "a.txt", "a", "First");
+    common::git(
+        &dir,
+        &[
+            "commit",
+            "--quiet",
+            "--amend",
+            "--no-edit",
+            "--date=2000-01-01T00:00:00+00:00",
+        ],
+    );
+    let amended = common::git(&dir, &["rev-parse", "HEAD"]);
+    let committed: i64 = common::git(&dir, &["log", "-1", "--format=%ct"])
+        .parse()
+        .unwrap();
+
+    let repo = GitRepo::open(dir.to_path_buf(), None, None).unwrap();
+    let metas = repo.to_info().commits_meta(&[amended]).unwrap();
+
+    for meta in [&metas[0], repo.head.as_ref().unwrap()] {
+        assert_eq!(meta.author_timestamp.unwrap().timestamp(), 946684800);
+        assert_eq!(meta.committer_timestamp.unwrap().timestamp(), committed);
+        assert_eq!(meta.timestamp, meta.committer_timestamp);
+    }
+}
+
+#[test]
+fn merge_pr_number_from_message() {
+    let pr = |message: &str| {
+        GitCommitMeta::new([0; 20])
+            .with_message(Some(message.to_string()))
+            .merge_pr_number()
+    };
+
+    assert_eq!(
+        pr("Merge pull request #123 from tjtelan/feature\n\nAdd feature"),
+        Some(123)
+    );
+    assert_eq!(pr("Add feature (#45)"), Some(45));
+    assert_eq!(
+        pr("Merge branch 'feature' into 'main'\n\nAdd feature\n\nSee merge request group/project!67"),
+        Some(67)
+    );
+
+    assert_eq!(pr("Fix the build (see #12 for details)"), None);
+    assert_eq!(pr("Bump version (#abc)"), None);
+    assert_eq!(GitCommitMeta::new([0; 20]).merge_pr_number(), None);
+}
+
+#[test]
+fn commit_meta_from_id_str() {
+    let id = "c097ad2a8c07bf2e3df64e6e603eee0473ad8133";
+
+    let meta = GitCommitMeta::from_id_str(id).unwrap();
+    assert_eq!(meta.id, id);
+    assert_eq!(meta, GitCommitMeta::new(git2::Oid::from_str(id).unwrap()));
+    assert_eq!(
+        GitCommitMeta::from_id_str(id.to_uppercase()).unwrap().id,
+        id
+    );
+
+    assert!(GitCommitMeta::from_id_str("c097ad2").is_err());
+    assert!(GitCommitMeta::from_id_str("z097ad2a8c07bf2e3df64e6e603eee0473ad8133").is_err());
+    assert!(GitCommitMeta::from_id_str("").is_err());
+}
+
+#[test]
+fn verify_head_against_expected_commit() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "a.txt", "a", "First");
+    let second = common::commit_file(&dir, "b.txt", "b", "Second");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    info.verify_head(&second).unwrap();
+    info.verify_head(&second[..7]).unwrap();
+
+    let err = info.verify_head(&first).unwrap_err().to_string();
+    assert!(err.contains(&first));
+    assert!(err.contains(&second));
+
+    assert!(info.verify_head("0000000").is_err());
+}
+
+#[test]
+fn commit_log_with_signature_presence() {
+    let dir = common::init_repo();
+    let unsigned = common::commit_file(&dir, "a.txt", "a", "Unsigned");
+    let tree = common::git(&dir, &["rev-parse", "HEAD^{tree}"]);
+
+    // Write a commit with a signature header by hand, since the signature itself isn't verified
+    let object = format!(
+        "tree {}\nparent {}\nauthor git-meta <git-meta@example.com> 1700000000 +0000\n\
+         committer git-meta <git-meta@example.com> 1700000000 +0000\n\
+         gpgsig -----BEGIN PGP SIGNATURE-----\n \n -----END PGP SIGNATURE-----\n\nSigned\n",
+        tree, unsigned
+    );
+    std::fs::write(dir.join("commit.txt"), object).unwrap();
+    let signed = common::git(&dir, &["hash-object", "-t", "commit", "-w", "commit.txt"]);
+    common::git(&dir, &["update-ref", "refs/heads/main", &signed]);
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let log = info.commit_log(None::<(&str, &str)>, None, true).unwrap();
+    assert_eq!(log.len(), 2);
+    assert_eq!(
+        (log[0].id.as_str(), log[0].signed),
+        (signed.as_str(), Some(true))
+    );
+    assert_eq!(
+        (log[1].id.as_str(), log[1].signed),
+        (unsigned.as_str(), Some(false))
+    );
+
+    // Signatures aren't checked by default
+    let log = info
+        .commit_log(Some((&unsigned, &signed)), Some(5), false)
+        .unwrap();
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0].signed, None);
+}
+
+#[test]
+fn commit_raw_header_keeps_signature_lines() {
+    let dir = common::init_repo();
+    let parent = common::commit_file(&dir, "a.txt", "a", "First");
+    let tree = common::git(&dir, &["rev-parse", "HEAD^{tree}"]);
+
+    let header = format!(
+        "tree {}\nparent {}\nauthor git-meta <git-meta@example.com> 1700000000 +0000\n\
+         committer git-meta <git-meta@example.com> 1700000000 +0000\n\
+         gpgsig -----BEGIN PGP SIGNATURE-----\n \n -----END PGP SIGNATURE-----\n",
+        tree, parent
+    );
+    std::fs::write(dir.join("commit.txt"), format!("{}\nSigned\n", header)).unwrap();
+    let signed = common::git(&dir, &["hash-object", "-t", "commit", "-w", "commit.txt"]);
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert_eq!(info.commit_raw_header(&signed).unwrap(), header);
+    assert!(info
+        .commit_raw_header(&parent)
+        .unwrap()
+        .starts_with(&format!("tree {}\n", tree)));
+    assert!(info.commit_raw_header("not-a-commit").is_err());
+}
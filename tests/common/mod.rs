@@ -0,0 +1,67 @@
+// Helpers for building throwaway repos on disk, so tests don't need the network.
+// Included by each test with `#[path = "common/mod.rs"]`, so not every helper is used everywhere
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use mktemp::Temp;
+
+/// Run `git` with `args` inside of `dir`. Panics if the command fails.
+/// Returns the trimmed stdout
+pub fn git<P: AsRef<Path>>(dir: P, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .current_dir(dir.as_ref())
+        .args(args)
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+/// Create an empty repo with `main` checked out
+pub fn init_repo() -> Temp {
+    let dir = Temp::new_dir().unwrap();
+
+    git(&dir, &["init", "--quiet", "--initial-branch=main"]);
+    git(&dir, &["config", "user.name", "git-meta"]);
+    git(&dir, &["config", "user.email", "git-meta@example.com"]);
+    git(&dir, &["config", "commit.gpgsign", "false"]);
+
+    dir
+}
+
+/// Write `contents` to `path` within `repo`, and commit it. Returns the new commit id
+pub fn commit_file<P: AsRef<Path>>(repo: P, path: &str, contents: &str, message: &str) -> String {
+    let file = repo.as_ref().join(path);
+
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    fs::write(file, contents).unwrap();
+
+    git(&repo, &["add", "--all"]);
+    git(&repo, &["commit", "--quiet", "--message", message]);
+
+    git(&repo, &["rev-parse", "HEAD"])
+}
+
+/// Clone `upstream` into a new temp dir, with the same user config as `init_repo()`
+pub fn clone_repo<P: AsRef<Path>>(upstream: P) -> Temp {
+    let dir = Temp::new_dir().unwrap();
+
+    git(&upstream, &["clone", "--quiet", ".", dir.to_str().unwrap()]);
+    git(&dir, &["config", "user.name", "git-meta"]);
+    git(&dir, &["config", "user.email", "git-meta@example.com"]);
+    git(&dir, &["config", "commit.gpgsign", "false"]);
+
+    dir
+}
@@ -0,0 +1,83 @@
+use git_meta::GitRepo;
+use mktemp::Temp;
+
+#[path = "common/mod.rs"]
+mod common;
+
+#[test]
+fn mirror_out_of_sync() {
+    let upstream = common::init_repo();
+    common::commit_file(&upstream, "README.md", "readme", "Initial commit");
+
+    let mirror = Temp::new_dir().unwrap();
+    common::git(
+        &upstream,
+        &["clone", "--quiet", "--bare", ".", mirror.to_str().unwrap()],
+    );
+
+    // Only upstream gets the new branch
+    common::git(&upstream, &["checkout", "--quiet", "-b", "feature"]);
+    common::commit_file(&upstream, "feature.txt", "feature", "Add feature");
+    common::git(&upstream, &["checkout", "--quiet", "main"]);
+
+    let upstream_clone = common::clone_repo(&upstream);
+    let mirror_clone = common::clone_repo(&mirror);
+
+    let ours = GitRepo::open(upstream_clone.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+    let theirs = GitRepo::open(mirror_clone.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let diff = ours.compare_heads(&theirs, None).unwrap();
+
+    assert!(!diff.is_empty());
+    assert!(diff.changed.is_empty());
+    assert!(diff.only_in_ours.contains_key("feature"));
+    assert!(diff.only_in_theirs.is_empty());
+
+    assert!(ours.compare_heads(&ours, None).unwrap().is_empty());
+
+    // Only the named branches are compared
+    assert!(ours
+        .compare_heads(&theirs, Some(vec!["main".to_string()]))
+        .unwrap()
+        .is_empty());
+    let diff = ours
+        .compare_heads(&theirs, Some(vec!["feature".to_string()]))
+        .unwrap();
+    assert_eq!(diff.only_in_ours.len(), 1);
+    assert!(diff.only_in_ours.contains_key("feature"));
+}
+
+#[test]
+fn branch_heads_pointing_at_annotated_tags() {
+    let upstream = common::init_repo();
+    let head = common::commit_file(&upstream, "README.md", "readme", "Initial commit");
+    common::git(
+        &upstream,
+        &["tag", "--annotate", "v1.0.0", "--message", "Release"],
+    );
+
+    // A branch ref can point at the tag object, rather than the commit.
+    // git refuses to write one with `update-ref`, so write the ref file directly
+    let tag_object = common::git(&upstream, &["rev-parse", "v1.0.0"]);
+    assert_ne!(tag_object, head);
+    std::fs::write(
+        upstream.join(".git/refs/heads/tagged"),
+        format!("{}\n", tag_object),
+    )
+    .unwrap();
+
+    let clone = common::clone_repo(&upstream);
+
+    let heads = GitRepo::open(clone.to_path_buf(), None, None)
+        .unwrap()
+        .to_info()
+        .get_remote_branch_head_refs(None)
+        .unwrap();
+
+    assert_eq!(heads["tagged"].id, head);
+    assert_eq!(heads["main"].id, head);
+}
@@ -0,0 +1,112 @@
+use git_meta::{CommitCount, GitRepo};
+use mktemp::Temp;
+
+#[path = "common/mod.rs"]
+mod common;
+
+#[test]
+fn count_commits_in_full_and_shallow_clones() {
+    let upstream = common::init_repo();
+    let first = common::commit_file(&upstream, "a.txt", "1", "First");
+    for n in 2..=5 {
+        common::commit_file(&upstream, "a.txt", &n.to_string(), "Next");
+    }
+
+    let full = GitRepo::open(upstream.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+    assert_eq!(
+        full.count_commits_between(&first, "main").unwrap(),
+        CommitCount {
+            count: 4,
+            complete: true
+        }
+    );
+
+    // Local clones ignore --depth unless given a file:// url
+    let shallow_dir = Temp::new_path();
+    common::git(
+        &upstream,
+        &[
+            "clone",
+            "--quiet",
+            "--depth=2",
+            &format!("file://{}", upstream.display()),
+            shallow_dir.to_str().unwrap(),
+        ],
+    );
+
+    let shallow = GitRepo::open(shallow_dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    // `first` was cut off by the shallow clone
+    assert_eq!(
+        shallow.count_commits_between(&first, "HEAD").unwrap(),
+        CommitCount {
+            count: 2,
+            complete: false
+        }
+    );
+
+    // The boundary commit is excluded, so nothing is missing
+    assert_eq!(
+        shallow.count_commits_between("HEAD~1", "HEAD").unwrap(),
+        CommitCount {
+            count: 1,
+            complete: true
+        }
+    );
+}
+
+#[test]
+fn commits_unique_to_branch() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "base.txt", "base", "Base");
+    common::git(&dir, &["checkout", "--quiet", "-b", "feature"]);
+    let feature1 = common::commit_file(&dir, "feature.txt", "1", "Feature 1");
+    let feature2 = common::commit_file(&dir, "feature.txt", "2", "Feature 2");
+    common::git(&dir, &["checkout", "--quiet", "main"]);
+    let main = common::commit_file(&dir, "main.txt", "main", "Main");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let ids = |branch, base| {
+        info.commits_unique_to(branch, base)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.id)
+            .collect::<Vec<String>>()
+    };
+
+    assert_eq!(ids("feature", "main"), vec![feature2, feature1]);
+    assert_eq!(ids("main", "feature"), vec![main]);
+    assert!(ids("main", "main").is_empty());
+}
+
+#[test]
+fn nth_parent_of_merge_commit() {
+    let dir = common::init_repo();
+    let base = common::commit_file(&dir, "base.txt", "base", "Base");
+    common::git(&dir, &["checkout", "--quiet", "-b", "feature"]);
+    let feature = common::commit_file(&dir, "feature.txt", "feature", "Feature");
+    common::git(&dir, &["checkout", "--quiet", "main"]);
+    let main = common::commit_file(&dir, "main.txt", "main", "Main");
+    common::git(&dir, &["merge", "--quiet", "--no-edit", "feature"]);
+    let merge = common::git(&dir, &["rev-parse", "HEAD"]);
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let parent = |commit: &str, n| info.nth_parent(commit, n).unwrap().map(|c| c.id);
+
+    assert_eq!(parent(&merge, 0), Some(merge.clone()));
+    assert_eq!(parent(&merge, 1), Some(main.clone()));
+    assert_eq!(parent(&merge, 2), Some(feature));
+    assert_eq!(parent(&merge, 3), None);
+    assert_eq!(parent(&main, 1), Some(base.clone()));
+    assert_eq!(parent(&base, 1), None);
+}
@@ -0,0 +1,116 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use git_meta::{CredentialProvider, GitCredentials, GitRepo};
+use mktemp::Temp;
+
+#[path = "common/mod.rs"]
+mod common;
+
+#[derive(Default)]
+struct FailingProvider {
+    calls: Arc<AtomicUsize>,
+}
+
+impl CredentialProvider for FailingProvider {
+    fn remote_callbacks(&self) -> color_eyre::eyre::Result<git2::RemoteCallbacks<'_>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Err(color_eyre::eyre::eyre!("No token available"))
+    }
+}
+
+#[derive(Default)]
+struct CountingProvider {
+    calls: Arc<AtomicUsize>,
+}
+
+impl CredentialProvider for CountingProvider {
+    fn remote_callbacks(&self) -> color_eyre::eyre::Result<git2::RemoteCallbacks<'_>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok(git2::RemoteCallbacks::new())
+    }
+}
+
+#[test]
+fn custom_credential_provider_is_used() {
+    let upstream = common::init_repo();
+    common::commit_file(&upstream, "README.md", "readme", "Initial commit");
+
+    let provider = FailingProvider::default();
+    let calls = provider.calls.clone();
+
+    let repo = GitRepo::new(upstream.to_str().unwrap())
+        .unwrap()
+        .with_credential_provider(provider);
+
+    // The provider survives conversions, and compares equal to itself
+    let info = repo.to_clone().to_info();
+    assert_eq!(repo, info.to_repo());
+
+    let err = info.remote_refs().unwrap_err();
+    assert!(format!("{:?}", err).contains("No token available"));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // Operations that use the git CLI can't use a custom provider
+    let tempdir = Temp::new_dir().unwrap();
+    let err = GitRepo::new("https://github.com/tjtelan/git-meta-rs.git")
+        .unwrap()
+        .with_credential_provider(FailingProvider::default())
+        .to_clone()
+        .git_clone_shallow(&tempdir)
+        .unwrap_err();
+    assert!(err.to_string().contains("not supported"));
+}
+
+#[test]
+fn credential_config_builds_fresh_callbacks() {
+    let creds = GitCredentials::UserPassPlaintext {
+        username: "user".to_string(),
+        password: "pass".to_string(),
+    };
+
+    let repo = GitRepo::new("https://example.com/repo.git")
+        .unwrap()
+        .with_credentials(Some(creds.clone()));
+    assert_eq!(repo.to_info().credential_config().credentials, Some(creds));
+
+    let provider = CountingProvider::default();
+    let calls = provider.calls.clone();
+
+    // The provider takes precedence, so the credentials aren't kept
+    let config = repo
+        .with_credential_provider(provider)
+        .to_info()
+        .credential_config();
+    assert_eq!(config.credentials, None);
+
+    config.callbacks().unwrap();
+    config.callbacks().unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn ssh_key_requires_ssh_support() {
+    let creds = GitCredentials::SshKey {
+        username: "git".to_string(),
+        public_key: None,
+        private_key: "/nonexistent/id_ed25519".into(),
+        passphrase: None,
+    };
+
+    let config = GitRepo::new("git@github.com:tjtelan/git-meta-rs.git")
+        .unwrap()
+        .with_credentials(Some(creds))
+        .to_info()
+        .credential_config();
+
+    // The key is only read once the remote asks for it
+    let callbacks = config.callbacks();
+    match callbacks {
+        Ok(_) => assert!(git2::Version::get().ssh()),
+        Err(e) => {
+            assert!(!git2::Version::get().ssh());
+            assert!(e.to_string().contains("without ssh support"));
+        }
+    }
+}
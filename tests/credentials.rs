@@ -0,0 +1,74 @@
+use std::env;
+
+use git_meta::{GitCredentials, GitRepo, GitRepoCloneRequest, GitRepoInfo, HostKeyPolicy};
+
+// Environment variables are process-wide, so all cases run in a single test
+#[test]
+fn credentials_from_env() {
+    for name in ["GIT_USERNAME", "GIT_PASSWORD", "GIT_TOKEN", "GITHUB_TOKEN"] {
+        env::remove_var(name);
+    }
+    assert_eq!(GitCredentials::from_env(), None);
+
+    env::set_var("GITHUB_TOKEN", "gh-token");
+    assert_eq!(
+        GitCredentials::from_env(),
+        Some(GitCredentials::UserPassPlaintext {
+            username: "x-access-token".to_string(),
+            password: "gh-token".to_string(),
+        })
+    );
+
+    env::set_var("GIT_USERNAME", "ci");
+    env::set_var("GIT_TOKEN", "git-token");
+    assert_eq!(
+        GitCredentials::from_env(),
+        Some(GitCredentials::UserPassPlaintext {
+            username: "ci".to_string(),
+            password: "git-token".to_string(),
+        })
+    );
+
+    env::set_var("GIT_PASSWORD", "hunter2");
+    assert_eq!(
+        GitCredentials::from_env(),
+        Some(GitCredentials::UserPassPlaintext {
+            username: "ci".to_string(),
+            password: "hunter2".to_string(),
+        })
+    );
+
+    for name in ["GIT_USERNAME", "GIT_PASSWORD", "GIT_TOKEN", "GITHUB_TOKEN"] {
+        env::remove_var(name);
+    }
+}
+
+#[test]
+fn host_key_policy_defaults_to_strict() {
+    let info = GitRepo::new("git@github.com:tjtelan/git-meta-rs.git")
+        .unwrap()
+        .to_info();
+    assert_eq!(info.host_key_policy, HostKeyPolicy::Strict);
+
+    // The policy is kept when a scratch clone is made from the info
+    let info = info.with_host_key_check(HostKeyPolicy::AcceptNew);
+    let request = GitRepoCloneRequest::from(&info);
+    assert_eq!(request.host_key_policy, HostKeyPolicy::AcceptNew);
+    assert_eq!(
+        GitRepoInfo::from(&request.with_host_key_check(HostKeyPolicy::AcceptAll)).host_key_policy,
+        HostKeyPolicy::AcceptAll
+    );
+
+    // git2 can't record new host keys, so it refuses `AcceptNew` for ssh urls before connecting
+    let err = info.build_git2_remotecallback().err().unwrap();
+    assert!(err.to_string().contains("AcceptNew"));
+    assert!(info.remote_branches().is_err());
+
+    // Host keys don't apply to https
+    assert!(GitRepo::new("https://github.com/tjtelan/git-meta-rs.git")
+        .unwrap()
+        .to_info()
+        .with_host_key_check(HostKeyPolicy::AcceptNew)
+        .build_git2_remotecallback()
+        .is_ok());
+}
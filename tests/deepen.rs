@@ -0,0 +1,30 @@
+use git_meta::GitRepo;
+use mktemp::Temp;
+
+#[path = "common/mod.rs"]
+mod common;
+
+#[test]
+fn deepen_shallow_clone() {
+    let tempdir = Temp::new_dir().unwrap();
+
+    let repo = GitRepo::new("https://github.com/tjtelan/git-meta-rs.git")
+        .unwrap()
+        .to_clone()
+        .git_clone_shallow(&tempdir)
+        .unwrap();
+
+    let commit_count = |dir: &Temp| -> u32 {
+        common::git(dir, &["rev-list", "--count", "HEAD"])
+            .parse()
+            .unwrap()
+    };
+
+    let before = commit_count(&tempdir);
+
+    let repo = repo.to_info().into_mut().deepen(5).unwrap();
+
+    // Merge commits mean we may see more than 5 additional commits
+    assert!(repo.is_shallow().unwrap());
+    assert!(commit_count(&tempdir) > before + 1);
+}
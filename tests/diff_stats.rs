@@ -0,0 +1,179 @@
+use std::path::PathBuf;
+
+use git_meta::{DiffStats, GitDiffOptions, GitRepo};
+
+#[path = "common/mod.rs"]
+mod common;
+
+fn hunks_from_cli(dir: &std::path::Path, args: &[&str]) -> usize {
+    let mut cmd = vec!["diff"];
+    cmd.extend_from_slice(args);
+    cmd.extend_from_slice(&["HEAD~1", "HEAD"]);
+
+    common::git(dir, &cmd)
+        .lines()
+        .filter(|l| l.starts_with("@@"))
+        .count()
+}
+
+#[test]
+fn diff_stats_match_git_diff() {
+    let dir = common::init_repo();
+
+    let before: Vec<String> = (1..=20).map(|n| format!("line {}\n", n)).collect();
+    let first = common::commit_file(&dir, "file.txt", &before.concat(), "First");
+
+    let mut after = before.clone();
+    after[2] = "changed 3\n".to_string();
+    after[10] = "changed 11\n".to_string();
+    after.push("line 21\n".to_string());
+    let second = common::commit_file(&dir, "file.txt", &after.concat(), "Second");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let stats = info
+        .diff_stats_between(&first, &second, &GitDiffOptions::new())
+        .unwrap();
+    assert_eq!(
+        stats,
+        DiffStats {
+            files_changed: 1,
+            insertions: 3,
+            deletions: 2,
+            hunks: hunks_from_cli(&dir, &[]),
+        }
+    );
+
+    for (context, interhunk) in [(0, 0), (1, 0), (1, 8), (5, 0)] {
+        let options = GitDiffOptions::new()
+            .with_context_lines(context)
+            .with_interhunk_lines(interhunk);
+
+        let stats = info.diff_stats_between(&first, &second, &options).unwrap();
+
+        let expected = hunks_from_cli(
+            &dir,
+            &[
+                &format!("-U{}", context),
+                &format!("--inter-hunk-context={}", interhunk),
+            ],
+        );
+        assert_eq!(
+            stats.hunks, expected,
+            "-U{} --inter-hunk-context={}",
+            context, interhunk
+        );
+        assert_eq!((stats.insertions, stats.deletions), (3, 2));
+    }
+}
+
+#[test]
+fn patch_between_matches_git_diff() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "file.txt", "one\ntwo\nthree\n", "First");
+    common::commit_file(&dir, "file.txt", "one\ntwo  \n3\n", "Second");
+    let last = common::commit_file(&dir, "vendor/dep.txt", "dep\n", "Vendor");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let patch = info
+        .patch_between(&first, &last, &GitDiffOptions::new())
+        .unwrap();
+    assert_eq!(patch.trim(), common::git(&dir, &["diff", &first, &last]));
+
+    let options = GitDiffOptions::new()
+        .with_exclude(vec![":!vendor".to_string()])
+        .with_ignore_whitespace(true);
+    let patch = info.patch_between(&first, &last, &options).unwrap();
+    assert_eq!(
+        patch.trim(),
+        common::git(&dir, &["diff", "-w", &first, &last, "--", ":!vendor"])
+    );
+    assert!(!patch.contains("+two  "));
+    assert!(patch.contains("+3"));
+}
+
+#[test]
+fn range_stats_match_git_log() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "a.txt", "one\n", "First");
+    common::commit_file(&dir, "a.txt", "one\ntwo\n", "Second");
+    common::git(&dir, &["checkout", "--quiet", "-b", "feature"]);
+    common::commit_file(&dir, "b.txt", "b\n", "Feature");
+    common::git(&dir, &["checkout", "--quiet", "main"]);
+    common::commit_file(&dir, "a.txt", "1\ntwo\n", "Third");
+    common::git(
+        &dir,
+        &["merge", "--quiet", "--no-ff", "-m", "Merge", "feature"],
+    );
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let stats = info.range_stats(first.as_str(), "main").unwrap();
+
+    // The merge commit is counted, but its changes were already counted on each side
+    assert_eq!(stats.commits, 4);
+    assert_eq!(stats.insertions, 3);
+    assert_eq!(stats.deletions, 1);
+    assert_eq!(
+        stats.files_touched.into_iter().collect::<Vec<_>>(),
+        vec![PathBuf::from("a.txt"), PathBuf::from("b.txt")]
+    );
+}
+
+#[test]
+fn same_tree_ignores_commit_metadata() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "a.txt", "a", "First");
+    common::git(&dir, &["commit", "--quiet", "--allow-empty", "-m", "Empty"]);
+    let empty = common::git(&dir, &["rev-parse", "HEAD"]);
+    let changed = common::commit_file(&dir, "a.txt", "changed", "Change");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert!(info.same_tree(&first, &empty).unwrap());
+    assert!(!info.same_tree(&empty, &changed).unwrap());
+}
+
+#[test]
+fn empty_commits_have_the_same_tree_as_their_parent() {
+    let dir = common::init_repo();
+    common::git(
+        &dir,
+        &["commit", "--quiet", "--allow-empty", "-m", "Empty root"],
+    );
+    let empty_root = common::git(&dir, &["rev-parse", "HEAD"]);
+    let first = common::commit_file(&dir, "a.txt", "a", "First");
+    common::git(&dir, &["commit", "--quiet", "--allow-empty", "-m", "Empty"]);
+    let empty = common::git(&dir, &["rev-parse", "HEAD"]);
+
+    // A revert restores the content of `first`, but still changes its parent
+    let changed = common::commit_file(&dir, "a.txt", "changed", "Change");
+    common::git(&dir, &["revert", "--quiet", "--no-edit", &changed]);
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert!(info.is_empty_commit(&empty_root).unwrap());
+    assert!(!info.is_empty_commit(&first).unwrap());
+    assert!(info.is_empty_commit(&empty).unwrap());
+    assert!(!info.is_empty_commit(&changed).unwrap());
+    assert!(!info.is_empty_commit("HEAD").unwrap());
+
+    // A root commit with files isn't empty
+    let other = common::init_repo();
+    let root = common::commit_file(&other, "a.txt", "a", "Root");
+    let info = GitRepo::open(other.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+    assert!(!info.is_empty_commit(&root).unwrap());
+}
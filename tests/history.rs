@@ -0,0 +1,230 @@
+use git_meta::GitRepo;
+
+#[path = "common/mod.rs"]
+mod common;
+
+#[test]
+fn path_history_follows_renames() {
+    let dir = common::init_repo();
+    let created = common::commit_file(&dir, "old/file.txt", "one\ntwo\nthree\n", "Create");
+    common::commit_file(&dir, "other.txt", "other", "Unrelated");
+    let edited = common::commit_file(&dir, "old/file.txt", "one\ntwo\nthree\nfour\n", "Edit");
+    common::git(&dir, &["mv", "old", "new"]);
+    common::git(&dir, &["commit", "--quiet", "-m", "Move"]);
+    let moved = common::git(&dir, &["rev-parse", "HEAD"]);
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let ids = |follow| {
+        info.path_history("new/file.txt", follow)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.id)
+            .collect::<Vec<String>>()
+    };
+
+    assert_eq!(ids(false), vec![moved.clone()]);
+    assert_eq!(ids(true), vec![moved.clone(), edited.clone(), created]);
+
+    assert_eq!(
+        info.last_commit_for_path("new/file.txt", true)
+            .unwrap()
+            .unwrap()
+            .id,
+        moved
+    );
+    assert_eq!(
+        info.last_commit_for_path("old/file.txt", false)
+            .unwrap()
+            .unwrap()
+            .id,
+        moved
+    );
+    assert!(info
+        .last_commit_for_path("missing.txt", false)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn root_commits_of_merged_unrelated_histories() {
+    let dir = common::init_repo();
+
+    let commit_at = |date: &str, message: &str| {
+        let status = std::process::Command::new("git")
+            .current_dir(&dir)
+            .env("GIT_COMMITTER_DATE", date)
+            .args(["commit", "--quiet", "--allow-empty", "-m", message])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        common::git(&dir, &["rev-parse", "HEAD"])
+    };
+
+    let first_root = commit_at("2020-01-01T00:00:00Z", "First root");
+    common::commit_file(&dir, "main.txt", "main", "Main");
+
+    common::git(&dir, &["checkout", "--quiet", "--orphan", "other"]);
+    common::git(&dir, &["rm", "--quiet", "-rf", "."]);
+    let second_root = commit_at("2021-01-01T00:00:00Z", "Second root");
+
+    common::git(&dir, &["checkout", "--quiet", "main"]);
+    common::git(
+        &dir,
+        &[
+            "merge",
+            "--quiet",
+            "--allow-unrelated-histories",
+            "-m",
+            "Merge",
+            "other",
+        ],
+    );
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let roots: Vec<String> = info
+        .root_commits()
+        .unwrap()
+        .into_iter()
+        .map(|c| c.id)
+        .collect();
+    assert_eq!(roots, vec![first_root, second_root]);
+}
+
+#[test]
+fn commits_touching_subdirectory() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "service-a/main.rs", "a", "Add service a");
+    let second = common::commit_file(&dir, "service-b/main.rs", "b", "Add service b");
+    let third = common::commit_file(&dir, "service-a/lib.rs", "lib", "Add lib to a");
+    let fourth = common::commit_file(&dir, "service-a/main.rs", "a2", "Edit service a");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let ids = |commits: Vec<git_meta::GitCommitMeta>| {
+        commits.into_iter().map(|c| c.id).collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        ids(info
+            .commits_touching_path("service-a", None::<(&str, &str)>, None)
+            .unwrap()),
+        vec![fourth.clone(), third.clone(), first.clone()]
+    );
+    assert_eq!(
+        ids(info
+            .commits_touching_path("service-b", None::<(&str, &str)>, None)
+            .unwrap()),
+        vec![second.clone()]
+    );
+    assert_eq!(
+        ids(info
+            .commits_touching_path("service-a", Some((&first, &fourth)), Some(1))
+            .unwrap()),
+        vec![fourth.clone()]
+    );
+    assert_eq!(
+        ids(info
+            .commits_touching_path("service-a", Some((&first, &third)), None)
+            .unwrap()),
+        vec![third]
+    );
+    assert!(info
+        .commits_touching_path("not-a-dir", None::<(&str, &str)>, None)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn find_first_commit_matching_predicate() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "a.txt", "a", "fix: first fix");
+    common::commit_file(&dir, "b.txt", "b", "feat: feature");
+    let second_fix = common::commit_file(&dir, "c.txt", "c", "fix: second fix");
+    common::commit_file(&dir, "d.txt", "d", "docs: readme");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let is_fix =
+        |c: &git_meta::GitCommitMeta| c.message.as_deref().is_some_and(|m| m.starts_with("fix:"));
+
+    assert_eq!(
+        info.find_commit_where(None::<&str>, is_fix)
+            .unwrap()
+            .unwrap()
+            .id,
+        second_fix
+    );
+    assert_eq!(
+        info.find_commit_where(Some(format!("{}^", second_fix)), is_fix)
+            .unwrap()
+            .unwrap()
+            .id,
+        first
+    );
+
+    // The walk stops at the first match
+    let mut visited = 0;
+    info.find_commit_where(None::<&str>, |c| {
+        visited += 1;
+        is_fix(c)
+    })
+    .unwrap();
+    assert_eq!(visited, 2);
+
+    assert_eq!(
+        info.find_commit_where(None::<&str>, |_| false).unwrap(),
+        None
+    );
+}
+
+#[test]
+fn commits_counted_by_author_email_domain() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "a.txt", "a", "First");
+
+    let commit_as = |author: &str| {
+        common::git(
+            &dir,
+            &[
+                "commit",
+                "--quiet",
+                "--allow-empty",
+                "--message",
+                "Work",
+                "--author",
+                author,
+            ],
+        );
+    };
+    commit_as("Alice <alice@Example.COM>");
+    commit_as("Bob <bob@example.com>");
+    commit_as("Carol <carol@other.org>");
+    commit_as("Nobody <>");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let counts = info.commits_by_domain(None::<(&str, &str)>).unwrap();
+    assert_eq!(counts.len(), 3);
+    assert_eq!(counts["example.com"], 3);
+    assert_eq!(counts["other.org"], 1);
+    assert_eq!(counts["unknown"], 1);
+
+    // The first commit, from the test user, is outside the range
+    let counts = info
+        .commits_by_domain(Some((first.as_str(), "HEAD")))
+        .unwrap();
+    assert_eq!(counts["example.com"], 2);
+    assert_eq!(counts.values().sum::<usize>(), 4);
+}
@@ -0,0 +1,60 @@
+use git_meta::{validate_message, MessageRules, MessageViolation};
+
+#[test]
+fn message_passes_default_rules() {
+    let rules = MessageRules::new();
+
+    assert!(validate_message("Fix the thing", &rules)
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        validate_message("\n\nBody only", &rules).unwrap(),
+        vec![MessageViolation::EmptySubject]
+    );
+}
+
+#[test]
+fn message_violations_are_reported() {
+    let rules = MessageRules::new()
+        .with_max_subject_length(20)
+        .with_require_body(true)
+        .with_allowed_types(vec!["feat".to_string(), "fix".to_string()]);
+
+    assert!(validate_message(
+        "feat(cli)!: Add flag\n\nExplains why.\n# A comment git strips",
+        &rules
+    )
+    .unwrap()
+    .is_empty());
+
+    assert_eq!(
+        validate_message("chore: Bump all of the dependencies\nNo blank line", &rules).unwrap(),
+        vec![
+            MessageViolation::SubjectTooLong {
+                length: 35,
+                max: 20
+            },
+            MessageViolation::MissingBlankLine,
+            MessageViolation::TypeNotAllowed {
+                found: "chore".to_string()
+            },
+        ]
+    );
+
+    assert_eq!(
+        validate_message("Add flag\n", &rules).unwrap(),
+        vec![MessageViolation::MissingBody, MessageViolation::MissingType]
+    );
+}
+
+#[test]
+fn unsatisfiable_rules_are_rejected() {
+    let rules = MessageRules::new().with_max_subject_length(0);
+    assert!(validate_message("Fix the thing", &rules).is_err());
+
+    let rules = MessageRules::new().with_allowed_types(vec!["feat".to_string(), "".to_string()]);
+    assert!(validate_message("feat: Add flag", &rules).is_err());
+
+    let rules = MessageRules::new().with_allowed_types(vec!["feat: ".to_string()]);
+    assert!(validate_message("feat: Add flag", &rules).is_err());
+}
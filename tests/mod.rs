@@ -1,4 +1,28 @@
+// Each test includes the `common` fixture helpers for itself
+#![allow(clippy::duplicate_mod)]
+
+mod commit_graph;
+mod commits_meta;
+mod compare_heads;
+mod count;
+mod credential_provider;
+mod credentials;
+mod deepen;
+mod diff_stats;
 mod expand;
+mod history;
+mod message;
 mod new_commits;
 mod open_repo;
 mod path_changed;
+mod proxy;
+mod range;
+mod refs;
+mod relationship;
+mod remote_refs;
+mod remote_tree;
+mod size;
+mod state;
+mod tags;
+mod timeout;
+mod tree;
@@ -1,8 +1,11 @@
 use std::path::PathBuf;
 
-use git_meta::GitRepo;
+use git_meta::{Delta, GitDiffOptions, GitRepo};
 use mktemp::Temp;
 
+#[path = "common/mod.rs"]
+mod common;
+
 #[test]
 fn files_changed_at_commit() {
     let tempdir = Temp::new_dir().unwrap();
@@ -64,7 +67,7 @@ fn files_changed_between_2_commits() {
         .git_clone(&tempdir)
         .unwrap();
 
-    let files = vec![
+    let files = [
         "CHANGELOG.md",
         "Cargo.toml",
         "README.md",
@@ -98,7 +101,7 @@ fn files_not_changed_between_2_commits() {
         .git_clone(&tempdir)
         .unwrap();
 
-    let files = vec!["LICENSE", ".gitignore"];
+    let files = ["LICENSE", ".gitignore"];
 
     for f in repo
         .to_info()
@@ -142,3 +145,301 @@ fn non_existent_dir_changed() {
 
     assert!(!repo.to_info().has_path_changed("not_a_dir").unwrap());
 }
+
+#[test]
+fn files_changed_between_commit_and_itself() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "a.txt", "a", "First");
+    let second = common::commit_file(&dir, "b.txt", "b", "Second");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert_eq!(
+        info.list_files_changed_between(&second, &second).unwrap(),
+        Some(vec![])
+    );
+}
+
+#[test]
+fn files_changed_between_commit_and_parent() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "a.txt", "a", "First");
+    let second = common::commit_file(&dir, "b.txt", "b", "Second");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert_eq!(
+        info.list_files_changed_between(&first, &second).unwrap(),
+        Some(vec![PathBuf::from("b.txt")])
+    );
+
+    // The root commit has no parent to compare against
+    assert_eq!(info.list_files_changed_at(&first).unwrap(), None);
+    assert_eq!(
+        info.list_files_changed_at(&second).unwrap(),
+        Some(vec![PathBuf::from("b.txt")])
+    );
+}
+
+#[test]
+fn files_changed_between_with_exclude() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "README.md", "readme", "First");
+    common::commit_file(&dir, "src/lib.rs", "lib", "Lib");
+    common::commit_file(&dir, "src/bin/main.rs", "main", "Main");
+    common::commit_file(&dir, "docs/guide.md", "guide", "Docs");
+    let last = common::commit_file(&dir, "Cargo.toml", "toml", "Manifest");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let exclude_src = GitDiffOptions::new().with_exclude(vec!["src/".to_string()]);
+    assert_eq!(
+        info.list_files_changed_between_with(&first, &last, &exclude_src)
+            .unwrap(),
+        Some(vec![
+            PathBuf::from("Cargo.toml"),
+            PathBuf::from("docs/guide.md")
+        ])
+    );
+
+    // Include is applied before exclude
+    let include_src = GitDiffOptions::new()
+        .with_pathspec(vec!["src".to_string()])
+        .with_exclude(vec![":!src/bin".to_string()]);
+    assert_eq!(
+        info.list_files_changed_between_with(&first, &last, &include_src)
+            .unwrap(),
+        Some(vec![PathBuf::from("src/lib.rs")])
+    );
+
+    let stats = info
+        .diff_stats_between(&first, &last, &exclude_src)
+        .unwrap();
+    assert_eq!(stats.files_changed, 2);
+    assert_eq!(stats.insertions, 2);
+}
+
+#[test]
+fn files_changed_in_workdir_since_commit() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "a.txt", "a", "First");
+    common::commit_file(&dir, "b.txt", "b", "Second");
+
+    std::fs::write(dir.join("a.txt"), "unstaged").unwrap();
+    std::fs::write(dir.join("c.txt"), "staged").unwrap();
+    common::git(&dir, &["add", "c.txt"]);
+    std::fs::write(dir.join("untracked.txt"), "untracked").unwrap();
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert_eq!(
+        info.diff_commit_to_workdir(None::<&str>).unwrap(),
+        vec![PathBuf::from("a.txt"), PathBuf::from("c.txt")]
+    );
+    assert_eq!(
+        info.diff_commit_to_workdir(Some(&first)).unwrap(),
+        vec![
+            PathBuf::from("a.txt"),
+            PathBuf::from("b.txt"),
+            PathBuf::from("c.txt")
+        ]
+    );
+}
+
+#[test]
+fn commit_change_summary_lists_files_and_message() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "a.txt", "a", "First");
+    common::commit_file(&dir, "b.txt", "b", "Second");
+    std::fs::write(dir.join("a.txt"), "changed").unwrap();
+    common::git(&dir, &["rm", "--quiet", "b.txt"]);
+    let third = common::commit_file(&dir, "c.txt", "c", "Third");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let summary = info.commit_change_summary(&third).unwrap();
+    assert_eq!(summary.commit.id, third);
+    assert_eq!(summary.commit.message, Some("Third\n".to_string()));
+    assert_eq!(
+        summary.files,
+        vec![
+            (PathBuf::from("a.txt"), Delta::Modified),
+            (PathBuf::from("b.txt"), Delta::Deleted),
+            (PathBuf::from("c.txt"), Delta::Added),
+        ]
+    );
+
+    // A root commit adds all of its files
+    assert_eq!(
+        info.commit_change_summary(&first).unwrap().files,
+        vec![(PathBuf::from("a.txt"), Delta::Added)]
+    );
+}
+
+#[test]
+fn diff_trees_by_id() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "a.txt", "a", "First");
+    let first_tree = common::git(&dir, &["rev-parse", "HEAD^{tree}"]);
+    let second = common::commit_file(&dir, "b.txt", "b", "Second");
+    let second_tree = common::git(&dir, &["rev-parse", "HEAD^{tree}"]);
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert_eq!(
+        info.diff_trees(&first_tree, &second_tree).unwrap(),
+        vec![(PathBuf::from("b.txt"), Delta::Added)]
+    );
+    assert_eq!(
+        info.diff_trees(&second_tree, &first_tree).unwrap(),
+        vec![(PathBuf::from("b.txt"), Delta::Deleted)]
+    );
+    assert!(info
+        .diff_trees(&first_tree, &first_tree)
+        .unwrap()
+        .is_empty());
+
+    let err = info.diff_trees(&first_tree, &second).unwrap_err();
+    assert!(err.to_string().contains("is not a tree"));
+    assert!(info.diff_trees(&first_tree, "not-an-id").is_err());
+}
+
+#[test]
+fn changed_files_iter_matches_list() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "a.txt", "a", "First");
+    for i in 0..200 {
+        std::fs::write(dir.join(format!("file{:03}.txt", i)), "contents").unwrap();
+    }
+    std::fs::write(dir.join("a.txt"), "changed").unwrap();
+    let last = common::commit_file(&dir, "b.txt", "b", "Many files");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let changes: Vec<_> = info
+        .changed_files_iter(&first, &last)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(changes.len(), 202);
+    assert_eq!(changes[0], (PathBuf::from("a.txt"), Delta::Modified));
+    assert_eq!(
+        changes
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>(),
+        info.list_files_changed_between(&first, &last)
+            .unwrap()
+            .unwrap()
+    );
+
+    // Stopping at the first interesting path
+    let found = info
+        .changed_files_iter(&first, &last)
+        .unwrap()
+        .map(|change| change.unwrap())
+        .find(|(path, _)| path == &PathBuf::from("file010.txt"));
+    assert_eq!(found, Some((PathBuf::from("file010.txt"), Delta::Added)));
+
+    assert!(info
+        .changed_files_iter(first.as_str(), "not-a-commit")
+        .is_err());
+}
+
+#[test]
+fn paths_changed_checks_many_paths_with_one_diff() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "services/api/main.rs", "api", "Api");
+    common::commit_file(&dir, "services/web/index.html", "web", "Web");
+    common::commit_file(&dir, "libs/shared/lib.rs", "shared", "Shared");
+    let last = common::commit_file(&dir, "services/web/app.js", "app", "App");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let paths = vec![
+        PathBuf::from("services/api"),
+        PathBuf::from("services/web"),
+        PathBuf::from("libs/shared"),
+        PathBuf::from("docs"),
+    ];
+    let changed = info.paths_changed(&paths, &first, &last).unwrap();
+
+    assert_eq!(changed.len(), 4);
+    assert!(!changed[&PathBuf::from("services/api")]);
+    assert!(changed[&PathBuf::from("services/web")]);
+    assert!(changed[&PathBuf::from("libs/shared")]);
+    assert!(!changed[&PathBuf::from("docs")]);
+
+    // Matches checking each path on its own
+    for path in &paths {
+        assert_eq!(
+            changed[path],
+            info.has_path_changed_between(path, &first, &last).unwrap()
+        );
+    }
+
+    assert!(info
+        .paths_changed(&paths, first.as_str(), "not-a-commit")
+        .is_err());
+}
+
+#[test]
+fn index_files_and_staged_changes() {
+    let dir = common::init_repo();
+
+    std::fs::write(dir.join("a.txt"), "a").unwrap();
+    common::git(&dir, &["add", "a.txt"]);
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    // Nothing is committed yet
+    assert_eq!(info.index_files().unwrap(), vec![PathBuf::from("a.txt")]);
+    assert_eq!(
+        info.staged_changes().unwrap(),
+        vec![(PathBuf::from("a.txt"), Delta::Added)]
+    );
+
+    common::git(&dir, &["commit", "--quiet", "--message", "First"]);
+    common::commit_file(&dir, "b.txt", "b", "Second");
+    assert!(info.staged_changes().unwrap().is_empty());
+
+    std::fs::write(dir.join("a.txt"), "staged").unwrap();
+    common::git(&dir, &["add", "a.txt"]);
+    common::git(&dir, &["rm", "--quiet", "b.txt"]);
+    std::fs::write(dir.join("c.txt"), "c").unwrap();
+    common::git(&dir, &["add", "c.txt"]);
+    // Neither unstaged nor untracked files are staged
+    std::fs::write(dir.join("c.txt"), "unstaged").unwrap();
+    std::fs::write(dir.join("untracked.txt"), "untracked").unwrap();
+
+    assert_eq!(
+        info.index_files().unwrap(),
+        vec![PathBuf::from("a.txt"), PathBuf::from("c.txt")]
+    );
+    assert_eq!(
+        info.staged_changes().unwrap(),
+        vec![
+            (PathBuf::from("a.txt"), Delta::Modified),
+            (PathBuf::from("b.txt"), Delta::Deleted),
+            (PathBuf::from("c.txt"), Delta::Added),
+        ]
+    );
+}
@@ -0,0 +1,97 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use git_meta::{GitRepo, ProxyConfig};
+use mktemp::Temp;
+
+#[path = "common/mod.rs"]
+mod common;
+
+/// Starts a proxy that answers `502 Bad Gateway` to every request.
+/// Returns its url, and a receiver of the first line of each request
+fn failing_proxy() -> (String, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+
+            let mut buf = [0; 1024];
+            let read = stream.read(&mut buf).unwrap();
+            let request = String::from_utf8_lossy(&buf[..read]).to_string();
+
+            let _ = stream.write_all(
+                b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            );
+
+            if sender
+                .send(request.lines().next().unwrap_or_default().to_string())
+                .is_err()
+            {
+                return;
+            }
+        }
+    });
+
+    (url, receiver)
+}
+
+#[test]
+fn clones_connect_through_proxy_url() {
+    // libgit2 only uses a proxy for https urls
+    let repo_url = "https://proxy-test.invalid/repo.git";
+
+    // git2
+    let (proxy, requests) = failing_proxy();
+    let tempdir = Temp::new_dir().unwrap();
+    assert!(GitRepo::new(repo_url)
+        .unwrap()
+        .to_clone()
+        .with_proxy(ProxyConfig::Url(proxy))
+        .git_clone(&tempdir)
+        .is_err());
+    assert_eq!(
+        requests.recv_timeout(Duration::from_secs(5)).unwrap(),
+        "CONNECT proxy-test.invalid:443 HTTP/1.1"
+    );
+
+    // git CLI
+    let (proxy, requests) = failing_proxy();
+    let tempdir = Temp::new_dir().unwrap();
+    assert!(GitRepo::new(repo_url)
+        .unwrap()
+        .to_clone()
+        .with_proxy(ProxyConfig::Url(proxy))
+        .git_clone_shallow(&tempdir)
+        .is_err());
+    assert_eq!(
+        requests.recv_timeout(Duration::from_secs(5)).unwrap(),
+        "CONNECT proxy-test.invalid:443 HTTP/1.1"
+    );
+}
+
+#[test]
+fn proxy_survives_conversions() {
+    let proxy = ProxyConfig::Url("http://proxy.example.com:3128".to_string());
+
+    let clone = GitRepo::new("https://github.com/tjtelan/git-meta-rs.git")
+        .unwrap()
+        .to_clone()
+        .with_proxy(proxy.clone());
+    assert_eq!(clone.to_info().proxy, proxy);
+    assert_eq!(clone.to_info().to_clone().proxy, proxy);
+
+    assert_eq!(
+        GitRepo::new("https://github.com/tjtelan/git-meta-rs.git")
+            .unwrap()
+            .to_info()
+            .proxy,
+        ProxyConfig::None
+    );
+}
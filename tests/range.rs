@@ -0,0 +1,57 @@
+use std::path::PathBuf;
+
+use git_meta::GitRepo;
+
+#[path = "common/mod.rs"]
+mod common;
+
+#[test]
+fn parse_two_and_three_dot_ranges() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "a.txt", "a", "First");
+    let second = common::commit_file(&dir, "b.txt", "b", "Second");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let (from, to, symmetric) = info.parse_range(&format!("{}..main", first)).unwrap();
+    assert_eq!(from.id, first);
+    assert_eq!(to.id, second);
+    assert!(!symmetric);
+
+    let (from, to, symmetric) = info.parse_range(&format!("{}...", &first[..7])).unwrap();
+    assert_eq!(from.id, first);
+    assert_eq!(to.id, second);
+    assert!(symmetric);
+
+    assert!(info.parse_range("main").is_err());
+    assert!(info.parse_range("main..not-a-branch").is_err());
+}
+
+#[test]
+fn files_changed_in_symmetric_range() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "base.txt", "base", "Base");
+    common::git(&dir, &["checkout", "--quiet", "-b", "feature"]);
+    common::commit_file(&dir, "feature.txt", "feature", "Feature");
+    common::git(&dir, &["checkout", "--quiet", "main"]);
+    common::commit_file(&dir, "main.txt", "main", "Main");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let two_dot = info
+        .files_changed_in_range("main..feature")
+        .unwrap()
+        .unwrap();
+    assert!(two_dot.contains(&PathBuf::from("feature.txt")));
+    assert!(two_dot.contains(&PathBuf::from("main.txt")));
+
+    let three_dot = info
+        .files_changed_in_range("main...feature")
+        .unwrap()
+        .unwrap();
+    assert_eq!(three_dot, vec![PathBuf::from("feature.txt")]);
+}
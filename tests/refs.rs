@@ -0,0 +1,79 @@
+use git_meta::{GitRepo, RefKind};
+
+#[path = "common/mod.rs"]
+mod common;
+
+#[test]
+fn list_all_refs_by_kind() {
+    let upstream = common::init_repo();
+    let head = common::commit_file(&upstream, "README.md", "readme", "Initial commit");
+
+    let clone = common::clone_repo(&upstream);
+    common::git(&clone, &["tag", "v1.0.0"]);
+    common::git(&clone, &["notes", "add", "-m", "A note"]);
+
+    let refs = GitRepo::open(clone.to_path_buf(), None, None)
+        .unwrap()
+        .to_info()
+        .list_all_refs()
+        .unwrap();
+
+    let find = |name: &str| refs.iter().find(|r| r.name == name).unwrap();
+
+    let head_ref = find("HEAD");
+    assert!(head_ref.is_symbolic);
+    assert_eq!(head_ref.target, "refs/heads/main");
+    assert_eq!(head_ref.kind, RefKind::Other);
+
+    let branch = find("refs/heads/main");
+    assert!(!branch.is_symbolic);
+    assert_eq!(branch.target, head);
+    assert_eq!(branch.kind, RefKind::Branch);
+
+    assert_eq!(find("refs/remotes/origin/main").kind, RefKind::RemoteBranch);
+    assert_eq!(find("refs/tags/v1.0.0").kind, RefKind::Tag);
+    assert_eq!(find("refs/notes/commits").kind, RefKind::Note);
+}
+
+#[test]
+fn resolve_default_branch_from_remote_head_or_candidates() {
+    let upstream = common::init_repo();
+    common::commit_file(&upstream, "README.md", "readme", "Initial commit");
+    common::git(&upstream, &["branch", "-m", "main", "trunk"]);
+
+    // The remote HEAD is preferred over the candidates
+    let clone = common::clone_repo(&upstream);
+    common::git(&clone, &["branch", "main"]);
+    let info = GitRepo::open(clone.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+    assert_eq!(
+        info.resolve_default_branch(&[]).unwrap(),
+        Some("trunk".to_string())
+    );
+
+    // The remote HEAD is skipped if it isn't a local branch
+    common::git(&clone, &["checkout", "--quiet", "-b", "dev"]);
+    common::git(&clone, &["branch", "--quiet", "-D", "main", "trunk"]);
+    let info = GitRepo::open(clone.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+    assert_eq!(info.resolve_default_branch(&[]).unwrap(), None);
+    assert_eq!(
+        info.resolve_default_branch(&["dev"]).unwrap(),
+        Some("dev".to_string())
+    );
+
+    // Without a remote, the first candidate that exists locally is used
+    let dir = common::init_repo();
+    common::commit_file(&dir, "README.md", "readme", "Initial commit");
+    common::git(&dir, &["branch", "-m", "main", "master"]);
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+    assert_eq!(
+        info.resolve_default_branch(&[]).unwrap(),
+        Some("master".to_string())
+    );
+    assert_eq!(info.resolve_default_branch(&["develop"]).unwrap(), None);
+}
@@ -0,0 +1,157 @@
+use git_meta::{BranchRelationship, GitRepo};
+
+#[path = "common/mod.rs"]
+mod common;
+
+fn relationship(clone: &std::path::Path) -> BranchRelationship {
+    GitRepo::open(clone.to_path_buf(), None, None)
+        .unwrap()
+        .to_info()
+        .into_mut()
+        .branch_relationship()
+        .unwrap()
+}
+
+#[test]
+fn branch_relationship_ahead_behind_diverged() {
+    let upstream = common::init_repo();
+    common::commit_file(&upstream, "README.md", "readme", "Initial commit");
+
+    let clone = common::clone_repo(&upstream);
+    assert_eq!(relationship(&clone), BranchRelationship::UpToDate);
+
+    common::commit_file(&upstream, "upstream.txt", "upstream", "Upstream commit");
+    assert_eq!(relationship(&clone), BranchRelationship::Behind(1));
+
+    common::git(&clone, &["pull", "--quiet", "--ff-only"]);
+    common::commit_file(&clone, "local.txt", "local", "Local commit");
+    assert_eq!(relationship(&clone), BranchRelationship::Ahead(1));
+
+    // Rewrite upstream history, like a force-push
+    common::git(
+        &upstream,
+        &["commit", "--quiet", "--amend", "-m", "Amended"],
+    );
+    assert_eq!(
+        relationship(&clone),
+        BranchRelationship::Diverged {
+            ahead: 2,
+            behind: 1
+        }
+    );
+}
+
+#[test]
+fn upstream_branch_of_local_branches() {
+    let upstream = common::init_repo();
+    common::commit_file(&upstream, "README.md", "readme", "Initial commit");
+
+    let clone = common::clone_repo(&upstream);
+    common::git(&clone, &["branch", "local-only"]);
+
+    let info = GitRepo::open(clone.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert_eq!(
+        info.upstream_branch("main").unwrap(),
+        Some("origin/main".to_string())
+    );
+    assert_eq!(info.upstream_branch("local-only").unwrap(), None);
+    assert!(info.upstream_branch("not-a-branch").is_err());
+}
+
+#[test]
+fn push_upstream_branch_in_triangular_workflow() {
+    let upstream = common::init_repo();
+    common::commit_file(&upstream, "README.md", "readme", "Initial commit");
+
+    let clone = common::clone_repo(&upstream);
+    common::git(&clone, &["branch", "local-only"]);
+
+    let info = GitRepo::open(clone.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    // Without push config, push goes to the fetch upstream
+    assert_eq!(
+        info.push_upstream_branch("main").unwrap(),
+        Some("origin/main".to_string())
+    );
+    assert_eq!(info.push_upstream_branch("local-only").unwrap(), None);
+    assert!(info.push_upstream_branch("not-a-branch").is_err());
+
+    common::git(&clone, &["config", "remote.pushDefault", "fork"]);
+    assert_eq!(
+        info.push_upstream_branch("local-only").unwrap(),
+        Some("fork/local-only".to_string())
+    );
+
+    // The per-branch push remote takes precedence
+    common::git(&clone, &["config", "branch.main.pushRemote", "mine"]);
+    assert_eq!(
+        info.push_upstream_branch("main").unwrap(),
+        Some("mine/main".to_string())
+    );
+}
+
+#[test]
+fn branch_merged_into_main() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "README.md", "readme", "First");
+    common::git(&dir, &["checkout", "--quiet", "-b", "merged"]);
+    common::commit_file(&dir, "merged.txt", "merged", "Merged work");
+    common::git(&dir, &["checkout", "--quiet", "-b", "unmerged", "main"]);
+    common::commit_file(&dir, "unmerged.txt", "unmerged", "Unmerged work");
+    common::git(&dir, &["checkout", "--quiet", "main"]);
+    common::git(
+        &dir,
+        &["merge", "--quiet", "--no-ff", "-m", "Merge", "merged"],
+    );
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert!(info.is_branch_merged("merged", "main").unwrap());
+    assert!(!info.is_branch_merged("unmerged", "main").unwrap());
+    assert!(info.is_branch_merged("main", "main").unwrap());
+    assert!(info.is_branch_merged("missing", "main").is_err());
+}
+
+#[test]
+fn branches_containing_commit() {
+    let upstream = common::init_repo();
+    let fix = common::commit_file(&upstream, "fix.txt", "fix", "Fix");
+
+    let clone = common::clone_repo(&upstream);
+    common::git(&clone, &["branch", "release", &fix]);
+    common::git(&clone, &["checkout", "--quiet", "-b", "feature"]);
+    let feature = common::commit_file(&clone, "feature.txt", "feature", "Feature");
+
+    let info = GitRepo::open(clone.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert_eq!(
+        info.branches_containing(&fix, git2::BranchType::Local)
+            .unwrap(),
+        vec!["feature", "main", "release"]
+    );
+    assert_eq!(
+        info.branches_containing(&feature, git2::BranchType::Local)
+            .unwrap(),
+        vec!["feature"]
+    );
+
+    // origin/HEAD isn't repeated alongside origin/main
+    assert_eq!(
+        info.branches_containing(&fix, git2::BranchType::Remote)
+            .unwrap(),
+        vec!["origin/main"]
+    );
+    assert!(info
+        .branches_containing(&feature, git2::BranchType::Remote)
+        .unwrap()
+        .is_empty());
+}
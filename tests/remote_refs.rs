@@ -0,0 +1,192 @@
+use git_meta::{GitRepo, PrRefKind, PullRequestRef};
+use mktemp::Temp;
+
+#[path = "common/mod.rs"]
+mod common;
+
+#[test]
+fn remote_refs_include_head_symref_and_tags() {
+    let upstream = common::init_repo();
+    let head = common::commit_file(&upstream, "README.md", "readme", "Initial commit");
+    common::git(&upstream, &["tag", "v1.0.0"]);
+    common::git(&upstream, &["update-ref", "refs/pull/1/head", &head]);
+
+    let clone = common::clone_repo(&upstream);
+
+    let from_url = GitRepo::new(upstream.to_str().unwrap())
+        .unwrap()
+        .to_info()
+        .remote_refs()
+        .unwrap();
+    let from_path = GitRepo::open(clone.to_path_buf(), None, None)
+        .unwrap()
+        .to_info()
+        .remote_refs()
+        .unwrap();
+
+    for refs in [from_url, from_path] {
+        let find = |name: &str| refs.iter().find(|r| r.name == name).unwrap();
+
+        assert_eq!(
+            find("HEAD").symref_target,
+            Some("refs/heads/main".to_string())
+        );
+        assert_eq!(find("HEAD").oid, head);
+        assert_eq!(find("refs/heads/main").symref_target, None);
+        assert_eq!(find("refs/tags/v1.0.0").oid, head);
+        assert_eq!(find("refs/pull/1/head").oid, head);
+    }
+}
+
+#[test]
+fn url_rewrites_follow_includes() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "README.md", "readme", "First");
+
+    common::git(
+        &dir,
+        &[
+            "config",
+            "url.https://mirror.example.com/.insteadOf",
+            "https://github.com/",
+        ],
+    );
+
+    // Rewrites in an included file are found too
+    let included = dir.join("rewrites.gitconfig");
+    std::fs::write(
+        &included,
+        "[url \"ssh://git@mirror.example.com/\"]\n\tinsteadOf = git@github.com:\n",
+    )
+    .unwrap();
+    common::git(
+        &dir,
+        &["config", "include.path", &included.to_string_lossy()],
+    );
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let rewrites = info.url_rewrites().unwrap();
+    assert!(rewrites.contains(&(
+        "https://github.com/".to_string(),
+        "https://mirror.example.com/".to_string()
+    )));
+    assert!(rewrites.contains(&(
+        "git@github.com:".to_string(),
+        "ssh://git@mirror.example.com/".to_string()
+    )));
+}
+
+#[test]
+fn clone_applies_global_url_rewrites() {
+    let upstream = common::init_repo();
+    let head = common::commit_file(&upstream, "README.md", "readme", "Initial commit");
+
+    // Rewrite a host that doesn't exist to the local upstream, in a temporary global config
+    let global = Temp::new_dir().unwrap();
+    std::fs::write(
+        global.join(".gitconfig"),
+        format!(
+            "[url \"{}\"]\n\tinsteadOf = https://rewrite-test.invalid/repo.git\n",
+            upstream.display()
+        ),
+    )
+    .unwrap();
+
+    // SAFETY: Only this test changes the global search path, and it's reset before returning
+    unsafe { git2::opts::set_search_path(git2::ConfigLevel::Global, global.as_path()).unwrap() };
+
+    let target = Temp::new_dir().unwrap();
+    let cloned = GitRepo::new("https://rewrite-test.invalid/repo.git")
+        .unwrap()
+        .to_clone()
+        .git_clone(&target);
+
+    unsafe { git2::opts::reset_search_path(git2::ConfigLevel::Global).unwrap() };
+
+    let cloned = cloned.unwrap();
+    assert_eq!(cloned.head.unwrap().id, head);
+
+    // The original url is kept in the clone's config
+    assert_eq!(
+        common::git(&target, &["config", "remote.origin.url"]),
+        "https://rewrite-test.invalid/repo.git"
+    );
+}
+
+#[test]
+fn remote_branches_without_clone() {
+    let upstream = common::init_repo();
+    let main = common::commit_file(&upstream, "README.md", "readme", "Initial commit");
+    common::git(&upstream, &["checkout", "--quiet", "-b", "feature"]);
+    let feature = common::commit_file(&upstream, "feature.txt", "feature", "Feature");
+    common::git(&upstream, &["tag", "v1.0.0"]);
+
+    let branches = GitRepo::new(upstream.to_str().unwrap())
+        .unwrap()
+        .to_info()
+        .remote_branches()
+        .unwrap();
+
+    assert_eq!(branches.len(), 2);
+    assert_eq!(branches["main"].id, main);
+    assert_eq!(branches["feature"].id, feature);
+
+    // Only the id is known without fetching the commit
+    assert_eq!(branches["main"].message, None);
+    assert_eq!(branches["main"].timestamp, None);
+
+    // A stale `path` isn't opened
+    let missing = Temp::new_dir().unwrap().to_path_buf();
+    let mut info = GitRepo::new(upstream.to_str().unwrap()).unwrap().to_info();
+    info.path = Some(missing.join("deleted"));
+    assert_eq!(info.remote_branches().unwrap(), branches);
+}
+
+#[test]
+fn pull_request_refs_from_github_and_gitlab_namespaces() {
+    let upstream = common::init_repo();
+    let first = common::commit_file(&upstream, "README.md", "readme", "Initial commit");
+    let second = common::commit_file(&upstream, "feature.txt", "feature", "Feature");
+
+    common::git(&upstream, &["update-ref", "refs/pull/10/head", &second]);
+    common::git(&upstream, &["update-ref", "refs/pull/10/merge", &first]);
+    common::git(&upstream, &["update-ref", "refs/pull/2/head", &first]);
+    common::git(
+        &upstream,
+        &["update-ref", "refs/merge-requests/7/head", &second],
+    );
+    common::git(
+        &upstream,
+        &["update-ref", "refs/pull/not-a-number/head", &first],
+    );
+
+    let pull_requests = GitRepo::new(upstream.to_str().unwrap())
+        .unwrap()
+        .to_info()
+        .list_pull_request_refs()
+        .unwrap();
+
+    assert_eq!(
+        pull_requests,
+        vec![
+            PullRequestRef {
+                number: 2,
+                kind: PrRefKind::GitHub,
+                oid: first,
+            },
+            PullRequestRef {
+                number: 7,
+                kind: PrRefKind::GitLab,
+                oid: second.clone(),
+            },
+            PullRequestRef {
+                number: 10,
+                kind: PrRefKind::GitHub,
+                oid: second,
+            },
+        ]
+    );
+}
@@ -0,0 +1,15 @@
+use std::path::PathBuf;
+
+use git_meta::GitRepo;
+
+#[test]
+fn remote_tree_listing_without_clone() {
+    let files = GitRepo::new("https://github.com/tjtelan/git-meta-rs.git")
+        .unwrap()
+        .to_info()
+        .remote_tree_listing("main")
+        .unwrap();
+
+    assert!(files.contains(&PathBuf::from("src/lib.rs")));
+    assert!(files.contains(&PathBuf::from("Cargo.toml")));
+}
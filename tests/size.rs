@@ -0,0 +1,54 @@
+use git_meta::GitRepo;
+
+#[path = "common/mod.rs"]
+mod common;
+
+#[test]
+fn repo_size_loose_and_packed() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "a.txt", "a", "First");
+    common::commit_file(&dir, "b.txt", "b", "Second");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    // 2 commits, 2 trees and 2 blobs
+    let loose = info.repo_size().unwrap();
+    assert_eq!(loose.object_count, 6);
+    assert!(loose.loose_size_bytes > 0);
+    assert_eq!(loose.packed_size_bytes, 0);
+
+    common::git(&dir, &["gc", "--quiet", "--prune=now"]);
+
+    let packed = info.repo_size().unwrap();
+    assert_eq!(packed.object_count, 6);
+    assert_eq!(packed.loose_size_bytes, 0);
+    assert!(packed.packed_size_bytes > 0);
+}
+
+#[test]
+fn repack_and_gc_pack_loose_objects() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "a.txt", "a", "First");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let repo_mut = info.clone().into_mut();
+
+    repo_mut.repack().unwrap();
+    let repacked = info.repo_size().unwrap();
+    assert_eq!(repacked.object_count, 3);
+    assert!(repacked.packed_size_bytes > 0);
+
+    common::commit_file(&dir, "b.txt", "b", "Second");
+    repo_mut.gc(true).unwrap();
+    assert_eq!(info.repo_size().unwrap().object_count, 6);
+
+    // Failures include git's exit status and stderr
+    std::fs::remove_dir_all(dir.join(".git/objects")).unwrap();
+    let err = repo_mut.gc(false).unwrap_err();
+    assert!(format!("{:#}", err).contains("git exited with"));
+}
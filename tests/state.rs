@@ -0,0 +1,250 @@
+use std::fs;
+use std::path::PathBuf;
+
+use git_meta::{GitRepo, RepoState};
+
+#[path = "common/mod.rs"]
+mod common;
+
+#[test]
+fn repo_state_during_merge_conflict() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "file.txt", "base", "Base");
+    common::git(&dir, &["checkout", "--quiet", "-b", "feature"]);
+    common::commit_file(&dir, "file.txt", "feature", "Feature");
+    common::git(&dir, &["checkout", "--quiet", "main"]);
+    common::commit_file(&dir, "file.txt", "main", "Main");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert_eq!(info.repo_state().unwrap(), RepoState::Clean);
+
+    // The merge conflicts, so git exits with an error and leaves the merge in progress
+    let merge = std::process::Command::new("git")
+        .args(["merge", "--quiet", "feature"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(!merge.status.success());
+
+    assert_eq!(info.repo_state().unwrap(), RepoState::Merge);
+
+    common::git(&dir, &["merge", "--abort"]);
+
+    assert_eq!(info.repo_state().unwrap(), RepoState::Clean);
+}
+
+#[test]
+fn merge_analysis_predicts_conflicts() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "file.txt", "base", "Base");
+    common::git(&dir, &["checkout", "--quiet", "-b", "ahead"]);
+    common::commit_file(&dir, "other.txt", "other", "Ahead");
+    common::git(&dir, &["checkout", "--quiet", "-b", "conflict", "main"]);
+    common::commit_file(&dir, "file.txt", "conflict", "Conflict");
+    common::git(&dir, &["checkout", "--quiet", "main"]);
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let ahead = info.merge_analysis("ahead").unwrap();
+    assert!(ahead.fast_forward);
+    assert!(ahead.conflicts.is_empty());
+
+    common::commit_file(&dir, "file.txt", "main", "Main");
+
+    let conflict = info.merge_analysis("conflict").unwrap();
+    assert!(!conflict.fast_forward);
+    assert_eq!(conflict.conflicts, vec![PathBuf::from("file.txt")]);
+
+    let clean = info.merge_analysis("ahead").unwrap();
+    assert!(!clean.fast_forward);
+    assert!(clean.conflicts.is_empty());
+
+    // The working tree is untouched
+    assert_eq!(info.repo_state().unwrap(), RepoState::Clean);
+    assert_eq!(common::git(&dir, &["status", "--porcelain"]), "");
+}
+
+#[test]
+fn clean_at_commit_without_local_changes() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "a.txt", "a", "First");
+    let second = common::commit_file(&dir, "b.txt", "b", "Second");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert!(!info.is_dirty().unwrap());
+    assert!(info.is_clean_at(&second).unwrap());
+    assert!(info.is_clean_at(&second[..7]).unwrap());
+    assert!(!info.is_clean_at(&first).unwrap());
+
+    // Untracked files don't make the checkout dirty
+    fs::write(dir.join("untracked.txt"), "untracked").unwrap();
+    assert!(info.is_clean_at(&second).unwrap());
+
+    fs::write(dir.join("a.txt"), "changed").unwrap();
+    assert!(info.is_dirty().unwrap());
+    assert!(!info.is_clean_at(&second).unwrap());
+
+    // Staged changes count too
+    common::git(&dir, &["add", "a.txt"]);
+    assert!(info.is_dirty().unwrap());
+
+    assert!(info.is_clean_at("not-a-commit").is_err());
+}
+
+#[test]
+fn path_ignored_by_gitignore_and_info_exclude() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, ".gitignore", "*.log\n", "Ignore logs");
+    std::fs::write(dir.join(".git/info/exclude"), "scratch/\n").unwrap();
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert!(info.is_path_ignored("build.log").unwrap());
+    assert!(info.is_path_ignored("nested/dir/build.log").unwrap());
+    assert!(info.is_path_ignored("scratch/notes.txt").unwrap());
+    assert!(!info.is_path_ignored("src/main.rs").unwrap());
+    assert!(!info.is_path_ignored(".gitignore").unwrap());
+}
+
+#[test]
+fn untracked_files_with_and_without_ignored() {
+    let dir = common::init_repo();
+    common::commit_file(
+        &dir,
+        ".gitignore",
+        "target/\n*.log\n",
+        "Ignore build output",
+    );
+
+    std::fs::create_dir_all(dir.join("target/debug")).unwrap();
+    std::fs::write(dir.join("target/debug/app"), "binary").unwrap();
+    std::fs::write(dir.join("build.log"), "log").unwrap();
+    std::fs::create_dir_all(dir.join("notes")).unwrap();
+    std::fs::write(dir.join("notes/todo.txt"), "todo").unwrap();
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert_eq!(
+        info.untracked_files(false).unwrap(),
+        vec![PathBuf::from("notes/todo.txt")]
+    );
+
+    let mut all = info.untracked_files(true).unwrap();
+    all.sort();
+    assert_eq!(
+        all,
+        vec![
+            PathBuf::from("build.log"),
+            PathBuf::from("notes/todo.txt"),
+            PathBuf::from("target/debug/app"),
+        ]
+    );
+}
+
+#[test]
+fn submodule_status_initialized_and_modified() {
+    let sub = common::init_repo();
+    common::commit_file(&sub, "lib.txt", "lib", "Lib");
+
+    let dir = common::init_repo();
+    common::commit_file(&dir, "README.md", "readme", "First");
+    common::git(
+        &dir,
+        &[
+            "-c",
+            "protocol.file.allow=always",
+            "submodule",
+            "add",
+            "--quiet",
+            &sub.to_string_lossy(),
+            "vendor/lib",
+        ],
+    );
+    common::git(&dir, &["commit", "--quiet", "-m", "Add submodule"]);
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let statuses = info.submodule_status().unwrap();
+    assert_eq!(statuses.len(), 1);
+
+    let (name, status) = &statuses[0];
+    assert_eq!(name, "vendor/lib");
+    assert!(status.in_head && status.in_index && status.in_config && status.in_workdir);
+    assert!(!status.wd_uninitialized);
+    assert!(!status.wd_wd_modified);
+
+    fs::write(dir.join("vendor/lib/lib.txt"), "changed").unwrap();
+
+    let (_, status) = &info.submodule_status().unwrap()[0];
+    assert!(status.wd_wd_modified);
+    assert!(!status.wd_modified);
+}
+
+#[test]
+fn state_fingerprint_tracks_branches_and_changes() {
+    let upstream = common::init_repo();
+    common::commit_file(&upstream, "file.txt", "base", "Base");
+    let clone = common::clone_repo(&upstream);
+
+    let fingerprint = |dir: &std::path::Path| {
+        GitRepo::open(dir.to_path_buf(), None, None)
+            .unwrap()
+            .to_info()
+            .state_fingerprint()
+            .unwrap()
+    };
+
+    // Identical checkouts
+    let clean = fingerprint(&clone);
+    assert_eq!(clean, fingerprint(&upstream));
+    assert_eq!(clean.len(), 40);
+
+    common::git(&clone, &["branch", "feature"]);
+    let with_branch = fingerprint(&clone);
+    assert_ne!(with_branch, clean);
+
+    fs::write(clone.join("file.txt"), "changed").unwrap();
+    let dirty = fingerprint(&clone);
+    assert_ne!(dirty, with_branch);
+
+    common::git(&clone, &["checkout", "--quiet", "--", "file.txt"]);
+    assert_eq!(fingerprint(&clone), with_branch);
+
+    common::git(&clone, &["branch", "-D", "--quiet", "feature"]);
+    assert_eq!(fingerprint(&clone), clean);
+}
+
+#[test]
+fn partial_clone_detected_from_config() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "file.txt", "base", "Base");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+    assert!(!info.is_partial_clone().unwrap());
+
+    common::git(&dir, &["config", "remote.origin.promisor", "false"]);
+    assert!(!info.is_partial_clone().unwrap());
+
+    common::git(&dir, &["config", "remote.origin.promisor", "true"]);
+    assert!(info.is_partial_clone().unwrap());
+
+    common::git(&dir, &["config", "--unset", "remote.origin.promisor"]);
+    common::git(&dir, &["config", "extensions.partialClone", "origin"]);
+    assert!(info.is_partial_clone().unwrap());
+}
@@ -0,0 +1,214 @@
+use git_meta::GitRepo;
+
+#[path = "common/mod.rs"]
+mod common;
+
+#[test]
+fn list_annotated_and_lightweight_tags() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "a.txt", "a", "First");
+    common::git(&dir, &["tag", "v0.1.0"]);
+    let second = common::commit_file(&dir, "b.txt", "b", "Second");
+    common::git(
+        &dir,
+        &["tag", "--annotate", "v0.2.0", "--message", "Release"],
+    );
+    common::git(&dir, &["tag", "other"]);
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let tags = info.list_tags(Some("v*")).unwrap();
+    assert_eq!(tags.len(), 2);
+
+    let lightweight = tags.iter().find(|t| t.name == "v0.1.0").unwrap();
+    assert!(!lightweight.annotated);
+    assert_eq!(lightweight.target.id, first);
+    assert!(lightweight.tagger.is_none());
+    assert!(lightweight.message.is_none());
+
+    let annotated = tags.iter().find(|t| t.name == "v0.2.0").unwrap();
+    assert!(annotated.annotated);
+    assert_eq!(annotated.target.id, second);
+    assert_eq!(
+        annotated.tagger.as_ref().unwrap().name,
+        Some("git-meta".to_string())
+    );
+    assert_eq!(annotated.message, Some("Release\n".to_string()));
+
+    assert_eq!(info.list_tags(None).unwrap().len(), 3);
+}
+
+#[test]
+fn tags_at_head() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "a.txt", "a", "First");
+    common::git(&dir, &["tag", "v0.1.0"]);
+    common::commit_file(&dir, "b.txt", "b", "Second");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+    assert!(info.head_tags().unwrap().is_empty());
+
+    common::git(&dir, &["tag", "v0.2.0"]);
+    common::git(
+        &dir,
+        &["tag", "--annotate", "release", "--message", "Release"],
+    );
+
+    let mut tags = info.head_tags().unwrap();
+    tags.sort();
+    assert_eq!(tags, vec!["release".to_string(), "v0.2.0".to_string()]);
+}
+
+#[test]
+fn tag_target_of_annotated_and_lightweight_tags() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "a.txt", "a", "First");
+    common::git(&dir, &["tag", "lightweight"]);
+    common::git(
+        &dir,
+        &["tag", "--annotate", "annotated", "--message", "Release"],
+    );
+    let second = common::commit_file(&dir, "b.txt", "b", "Second");
+    // A branch with the same name as a tag shouldn't be resolved instead
+    common::git(&dir, &["branch", "annotated"]);
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert_eq!(info.tag_target("lightweight").unwrap().id, first);
+    assert_eq!(info.tag_target("annotated").unwrap().id, first);
+    assert_ne!(info.tag_target("annotated").unwrap().id, second);
+    assert!(info.tag_target("missing").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_names_dont_fail_listings() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::process::Command;
+
+    let upstream = common::init_repo();
+    let head = common::commit_file(&upstream, "README.md", "readme", "Initial commit");
+    common::git(&upstream, &["tag", "v1.0.0"]);
+
+    let git_with_bytes = |dir: &std::path::Path, args: &[&[u8]]| {
+        let status = Command::new("git")
+            .args(args.iter().map(|a| OsStr::from_bytes(a)))
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+
+    git_with_bytes(
+        &upstream,
+        &[b"update-ref", b"refs/tags/bad-\xff", head.as_bytes()],
+    );
+
+    let info = GitRepo::open(upstream.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let tags = info.list_tags(None).unwrap();
+    assert_eq!(tags.len(), 1);
+    assert_eq!(tags[0].name, "v1.0.0");
+    assert_eq!(info.head_tags().unwrap(), vec!["v1.0.0".to_string()]);
+
+    // A remote name that isn't valid utf-8 is converted lossily, or available as raw bytes
+    let clone = common::clone_repo(&upstream);
+    git_with_bytes(&clone, &[b"config", b"branch.main.remote", b"or\xffigin"]);
+
+    let repo = GitRepo::to_repository_from_path(clone.to_path_buf()).unwrap();
+    let info = GitRepo::new("https://github.com/tjtelan/git-meta-rs.git")
+        .unwrap()
+        .to_info();
+
+    assert_eq!(info.get_remote_name(&repo).unwrap(), "or\u{fffd}igin");
+    assert_eq!(
+        info.get_remote_name_bytes(&repo).unwrap(),
+        b"or\xffigin".to_vec()
+    );
+
+    // The upstream remote of a branch whose name isn't valid utf-8 is still found
+    git_with_bytes(&clone, &[b"checkout", b"--quiet", b"-b", b"feature-\xff"]);
+    git_with_bytes(
+        &clone,
+        &[b"config", b"branch.feature-\xff.remote", b"origin"],
+    );
+
+    let repo = GitRepo::to_repository_from_path(clone.to_path_buf()).unwrap();
+    assert_eq!(info.get_remote_name(&repo).unwrap(), "origin");
+
+    // Without an upstream, it's still an error
+    git_with_bytes(&clone, &[b"checkout", b"--quiet", b"-b", b"other-\xff"]);
+    let repo = GitRepo::to_repository_from_path(clone.to_path_buf()).unwrap();
+    assert!(info.get_remote_name_bytes(&repo).is_err());
+}
+
+#[test]
+fn tags_in_commit_range() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "a.txt", "a", "First");
+    common::git(&dir, &["tag", "v1.0.0"]);
+    common::commit_file(&dir, "b.txt", "b", "Second");
+    common::git(
+        &dir,
+        &["tag", "-a", "-m", "Release candidate", "v1.1.0-rc1"],
+    );
+    common::git(&dir, &["tag", "marker"]);
+    common::commit_file(&dir, "c.txt", "c", "Third");
+    common::git(&dir, &["tag", "v1.1.0"]);
+    common::commit_file(&dir, "d.txt", "d", "Unreleased");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let names: Vec<String> = info
+        .tags_in_range("v1.0.0", "main")
+        .unwrap()
+        .into_iter()
+        .map(|t| t.name)
+        .collect();
+    assert_eq!(names, vec!["v1.1.0", "marker", "v1.1.0-rc1"]);
+
+    assert!(info.tags_in_range("v1.1.0", "main").unwrap().is_empty());
+}
+
+#[test]
+fn fetch_tags_without_branches() {
+    let upstream = common::init_repo();
+    common::commit_file(&upstream, "README.md", "readme", "Initial commit");
+
+    let clone = common::clone_repo(&upstream);
+    let clone_head = common::git(&clone, &["rev-parse", "HEAD"]);
+
+    let tagged = common::commit_file(&upstream, "release.txt", "release", "Release commit");
+    common::git(
+        &upstream,
+        &["tag", "--annotate", "v1.0.0", "--message", "First release"],
+    );
+
+    let info = GitRepo::open(clone.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+    assert!(info.list_tags(None).unwrap().is_empty());
+
+    let tags = info.clone().into_mut().fetch_tags().unwrap();
+    assert_eq!(tags.len(), 1);
+    assert_eq!(tags[0].name, "v1.0.0");
+    assert_eq!(tags[0].target.id, tagged);
+    assert_eq!(tags[0].message, Some("First release\n".to_string()));
+
+    // Branches are left alone
+    assert_eq!(
+        common::git(&clone, &["rev-parse", "origin/main"]),
+        clone_head
+    );
+}
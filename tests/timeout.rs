@@ -0,0 +1,46 @@
+use std::net::TcpListener;
+use std::time::{Duration, Instant};
+
+use git_meta::{GitMetaError, GitRepo};
+
+#[path = "common/mod.rs"]
+mod common;
+
+#[test]
+fn remote_heads_connect_timeout() {
+    // Accepts connections (through the backlog) but never responds
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let dir = common::init_repo();
+    common::commit_file(&dir, "README.md", "readme", "Initial commit");
+    common::git(
+        &dir,
+        &[
+            "remote",
+            "add",
+            "origin",
+            &format!("git://127.0.0.1:{}/repo.git", port),
+        ],
+    );
+    common::git(&dir, &["config", "branch.main.remote", "origin"]);
+    common::git(&dir, &["config", "branch.main.merge", "refs/heads/main"]);
+
+    let timeout = Duration::from_millis(500);
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info()
+        .with_connect_timeout(timeout);
+
+    let start = Instant::now();
+    let err = info.get_remote_branch_head_refs(None).unwrap_err();
+
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert_eq!(
+        err.downcast_ref::<GitMetaError>(),
+        Some(&GitMetaError::ConnectTimeout {
+            url: format!("git://127.0.0.1:{}/repo.git", port),
+            timeout
+        })
+    );
+}
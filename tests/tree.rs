@@ -0,0 +1,165 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use git_meta::{GitRepo, TreeEntryKind};
+
+#[path = "common/mod.rs"]
+mod common;
+
+#[cfg(unix)]
+#[test]
+fn symlinks_listed_distinctly() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "src/file.txt", "contents", "Add file");
+    std::os::unix::fs::symlink("src/file.txt", dir.join("link")).unwrap();
+    let commit = common::commit_file(&dir, "README.md", "readme", "Add link");
+
+    let repo = GitRepo::open(dir.to_path_buf(), None, None).unwrap();
+
+    let entries = repo.to_info().list_tree(&commit, None::<PathBuf>).unwrap();
+
+    let link = entries
+        .iter()
+        .find(|e| e.path == Path::new("link"))
+        .unwrap();
+    assert_eq!(link.kind, TreeEntryKind::Symlink);
+
+    let src = entries.iter().find(|e| e.path == Path::new("src")).unwrap();
+    assert_eq!(src.kind, TreeEntryKind::Directory);
+
+    let nested = repo.to_info().list_tree(&commit, Some("src")).unwrap();
+    assert_eq!(nested[0].path, PathBuf::from("src/file.txt"));
+    assert_eq!(nested[0].kind, TreeEntryKind::File);
+}
+
+#[cfg(unix)]
+#[test]
+fn file_at_commit_symlink_resolution() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "src/file.txt", "contents", "Add file");
+    std::os::unix::fs::symlink("file.txt", dir.join("src/link")).unwrap();
+    std::os::unix::fs::symlink("../outside", dir.join("escape")).unwrap();
+    let commit = common::commit_file(&dir, "README.md", "readme", "Add links");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    assert_eq!(
+        info.file_at_commit(&commit, "src/link", false).unwrap(),
+        Some(b"file.txt".to_vec())
+    );
+    assert_eq!(
+        info.file_at_commit(&commit, "src/link", true).unwrap(),
+        Some(b"contents".to_vec())
+    );
+    assert_eq!(info.file_at_commit(&commit, "escape", true).unwrap(), None);
+    assert_eq!(info.file_at_commit(&commit, "missing", true).unwrap(), None);
+}
+
+#[test]
+fn file_extension_stats_of_commit() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "src/lib.rs", "", "Add files");
+    common::commit_file(&dir, "src/main.RS", "", "Add files");
+    common::commit_file(&dir, "README.md", "", "Add files");
+    common::commit_file(&dir, ".gitignore", "", "Add files");
+    let head = common::commit_file(&dir, "Makefile", "", "Add files");
+
+    let stats = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info()
+        .file_extension_stats(&head)
+        .unwrap();
+
+    assert_eq!(stats.get("rs"), Some(&2));
+    assert_eq!(stats.get("md"), Some(&1));
+    assert_eq!(stats.get(""), Some(&2));
+    assert_eq!(stats.len(), 3);
+}
+
+#[test]
+fn blob_info_at_commit() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "a.txt", "same", "Add a");
+    common::commit_file(&dir, "dir/b.txt", "same", "Add b");
+    std::fs::write(dir.join("image.bin"), [0u8, 159, 146, 150]).unwrap();
+    let commit = common::commit_file(&dir, "c.txt", "different", "Add c and binary");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let a = info.blob_info(&commit, "a.txt").unwrap().unwrap();
+    let b = info.blob_info(&commit, "dir/b.txt").unwrap().unwrap();
+    let c = info.blob_info(&commit, "c.txt").unwrap().unwrap();
+
+    assert_eq!(a.oid, b.oid);
+    assert_ne!(a.oid, c.oid);
+    assert_eq!(a.size, 4);
+    assert!(!a.is_binary);
+
+    assert!(
+        info.blob_info(&commit, "image.bin")
+            .unwrap()
+            .unwrap()
+            .is_binary
+    );
+    assert!(info.blob_info(&commit, "missing.txt").unwrap().is_none());
+    assert!(info.blob_info(&commit, "dir").is_err());
+}
+
+#[test]
+fn list_all_files_recursively() {
+    let dir = common::init_repo();
+    common::commit_file(&dir, "README.md", "readme", "Add files");
+    common::commit_file(&dir, "src/lib.rs", "lib", "Add files");
+    let commit = common::commit_file(&dir, "src/bin/main.rs", "main", "Add files");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let mut all = info.list_all_files(&commit, None::<PathBuf>).unwrap();
+    all.sort();
+    assert_eq!(
+        all,
+        vec![
+            PathBuf::from("README.md"),
+            PathBuf::from("src/bin/main.rs"),
+            PathBuf::from("src/lib.rs"),
+        ]
+    );
+
+    let mut src = info.list_all_files(&commit, Some("src")).unwrap();
+    src.sort();
+    assert_eq!(
+        src,
+        vec![
+            PathBuf::from("src/bin/main.rs"),
+            PathBuf::from("src/lib.rs")
+        ]
+    );
+
+    assert!(info.list_all_files(&commit, Some("README.md")).is_err());
+}
+
+#[test]
+fn file_reader_at_commit_reads_contents() {
+    let dir = common::init_repo();
+    let first = common::commit_file(&dir, "data.bin", "first contents", "First");
+    common::commit_file(&dir, "data.bin", "second contents", "Second");
+
+    let info = GitRepo::open(dir.to_path_buf(), None, None)
+        .unwrap()
+        .to_info();
+
+    let mut contents = String::new();
+    info.file_reader_at_commit(&first, "data.bin")
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    assert_eq!(contents, "first contents");
+
+    assert!(info.file_reader_at_commit(&first, "missing.bin").is_err());
+}